@@ -4,6 +4,7 @@ use clap::{Arg, Command};
 pub enum DefaultValue {
     String(String),
     Bool(bool),
+    None,
 }
 
 #[derive(Clone)]
@@ -28,6 +29,7 @@ pub struct CmdArgEntry {
     pub long: String,
     pub takes_value: bool,
     pub required: ArgRequired,
+    pub multiple: bool,
 }
 
 impl CmdArgEntry {
@@ -44,8 +46,15 @@ impl CmdArgEntry {
             long: String::from(long),
             takes_value,
             required,
+            multiple: false,
         }
     }
+
+    // accepts several values, e.g. --input-files a.csv b.csv
+    pub fn multiple(mut self) -> Self {
+        self.multiple = true;
+        self
+    }
 }
 
 #[derive(Clone)]
@@ -73,6 +82,17 @@ pub fn command<'a>() -> Command<'a> {
         .about("Extract and Load data")
 }
 
+// arguments of the process itself, shared by every extractor and loader
+pub fn process_args() -> CmdArg {
+    CmdArg::new(vec![CmdArgEntry::new(
+        "shutdown-timeout",
+        "On SIGINT or SIGTERM, wait this long for buffered records to be loaded (e.g. 30s)",
+        "shutdown-timeout",
+        true,
+        ArgRequired::False(DefaultValue::String(String::from("30s"))),
+    )])
+}
+
 pub fn arg_parse<'a>(cmd_args: &'a CmdArg, mut cmd: Command<'a>) -> Command<'a> {
     let args: Vec<Arg> = cmd_args
         .entries()
@@ -82,7 +102,9 @@ pub fn arg_parse<'a>(cmd_args: &'a CmdArg, mut cmd: Command<'a>) -> Command<'a>
                 .help(e.help.as_str())
                 .long(e.long.as_str())
                 .takes_value(e.takes_value)
-                .required(e.required.as_bool());
+                .required(e.required.as_bool())
+                .multiple_values(e.multiple)
+                .multiple_occurrences(e.multiple);
             match e.required {
                 ArgRequired::True => a,
                 ArgRequired::False(ref val) => match val {
@@ -94,6 +116,7 @@ pub fn arg_parse<'a>(cmd_args: &'a CmdArg, mut cmd: Command<'a>) -> Command<'a>
                             a
                         }
                     }
+                    DefaultValue::None => a,
                 },
             }
         })
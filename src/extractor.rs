@@ -1,7 +1,13 @@
 #[cfg(feature = "extractor-local")]
+mod encoding;
+#[cfg(feature = "extractor-local")]
+mod ledger;
+#[cfg(feature = "extractor-local")]
 mod local;
+#[cfg(any(feature = "extractor-local", feature = "extractor-postgresql"))]
+mod metadata;
 #[cfg(feature = "extractor-local")]
-pub use local::Extractor;
+pub use local::{Extractor, WatchOptions};
 #[cfg(feature = "extractor-postgresql")]
 mod postgresql;
 #[cfg(feature = "extractor-postgresql")]
@@ -0,0 +1,141 @@
+use crate::cli::{ArgRequired, CmdArg, CmdArgEntry, DefaultValue};
+use clap::ArgMatches;
+use encoding_rs::{CoderResult, Decoder, DecoderResult, Encoding};
+use std::io::{self, Read};
+
+const BUFFER_SIZE: usize = 64 * 1024;
+
+// Transcodes a stream to UTF-8, a BOM in the input overrides the given encoding.
+struct Transcoder {
+    inner: Box<dyn Read + Send>,
+    decoder: Decoder,
+    reject_invalid: bool,
+    position: usize,
+    decoded: Vec<u8>,
+    decoded_pos: usize,
+    finished: bool,
+}
+
+impl Transcoder {
+    fn decode_chunk(&mut self) -> io::Result<()> {
+        let mut input = vec![0; BUFFER_SIZE];
+        let n = self.inner.read(&mut input)?;
+        let last = n == 0;
+        let mut src = &input[..n];
+        let capacity = self.decoder.max_utf8_buffer_length(n).unwrap_or(n * 3 + 16);
+        let mut decoded = String::with_capacity(capacity);
+        loop {
+            if self.reject_invalid {
+                let (result, read) =
+                    self.decoder.decode_to_string_without_replacement(src, &mut decoded, last);
+                self.position += read;
+                src = &src[read..];
+                match result {
+                    DecoderResult::InputEmpty => break,
+                    DecoderResult::OutputFull => decoded.reserve(src.len() * 3 + 16),
+                    DecoderResult::Malformed(_, _) => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!(
+                                "invalid {} sequence before byte {}",
+                                self.decoder.encoding().name(),
+                                self.position
+                            ),
+                        ))
+                    }
+                }
+            } else {
+                let (result, read, _) = self.decoder.decode_to_string(src, &mut decoded, last);
+                src = &src[read..];
+                match result {
+                    CoderResult::InputEmpty => break,
+                    CoderResult::OutputFull => decoded.reserve(src.len() * 3 + 16),
+                }
+            }
+        }
+        self.decoded = decoded.into_bytes();
+        self.decoded_pos = 0;
+        self.finished = last;
+        Ok(())
+    }
+}
+
+impl Read for Transcoder {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.decoded_pos == self.decoded.len() {
+            if self.finished {
+                return Ok(0);
+            }
+            self.decode_chunk()?;
+        }
+        let n = buf.len().min(self.decoded.len() - self.decoded_pos);
+        buf[..n].copy_from_slice(&self.decoded[self.decoded_pos..self.decoded_pos + n]);
+        self.decoded_pos += n;
+        Ok(n)
+    }
+}
+
+#[derive(Clone)]
+pub struct InputEncoding {
+    // None reads the input bytes as they are, e.g. for Parquet input
+    encoding: Option<&'static Encoding>,
+    reject_invalid: bool,
+}
+
+impl InputEncoding {
+    pub fn new(matches: &ArgMatches) -> Self {
+        Self {
+            encoding: matches.value_of("input-encoding").map(|label| match label {
+                // UTF-8 unless there is a BOM
+                "auto" => encoding_rs::UTF_8,
+                _ => Encoding::for_label(label.as_bytes())
+                    .unwrap_or_else(|| panic!("unknown input encoding: {}", label)),
+            }),
+            reject_invalid: match matches.value_of("input-encoding-errors").unwrap() {
+                "reject" => true,
+                "replace" => false,
+                unknown => panic!("unknown input-encoding-errors: {}", unknown),
+            },
+        }
+    }
+
+    pub fn cmd_args() -> CmdArg {
+        CmdArg::new(vec![
+            CmdArgEntry::new(
+                "input-encoding",
+                "Transcode text input from this encoding (auto, utf-8, shift_jis, euc-jp, latin1, utf-16le, ...), a BOM takes precedence",
+                "input-encoding",
+                true,
+                ArgRequired::False(DefaultValue::None),
+            ),
+            CmdArgEntry::new(
+                "input-encoding-errors",
+                "Fail on invalid byte sequences (reject) or replace them with U+FFFD (replace)",
+                "input-encoding-errors",
+                true,
+                ArgRequired::False(DefaultValue::String(String::from("reject"))),
+            ),
+        ])
+    }
+
+    pub fn transcodes(&self) -> bool {
+        self.encoding.is_some()
+    }
+
+    // the input as UTF-8 without a BOM
+    pub fn decode(&self, file: Box<dyn Read + Send>) -> Box<dyn Read + Send> {
+        let encoding = match self.encoding {
+            Some(encoding) => encoding,
+            None => return file,
+        };
+        Box::new(Transcoder {
+            inner: file,
+            decoder: encoding.new_decoder(),
+            reject_invalid: self.reject_invalid,
+            position: 0,
+            decoded: vec![],
+            decoded_pos: 0,
+            finished: false,
+        })
+    }
+}
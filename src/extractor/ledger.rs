@@ -0,0 +1,233 @@
+use crate::cli::{ArgRequired, CmdArg, CmdArgEntry, DefaultValue};
+use chrono::Utc;
+use clap::ArgMatches;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+// An input file as it was when it was read, recorded once it is loaded
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Entry {
+    path: String,
+    size: u64,
+    checksum: String,
+    loaded_at: String,
+    // compared at commit time, so that a file changed while it was loaded isn't recorded
+    #[serde(skip)]
+    modified: Option<SystemTime>,
+}
+
+impl Entry {
+    fn of_file(path: &str, with_checksum: bool) -> io::Result<Self> {
+        if path == "-" {
+            return Ok(Self {
+                path: String::from(path),
+                size: 0,
+                checksum: String::new(),
+                loaded_at: String::new(),
+                modified: None,
+            });
+        }
+        let metadata = fs::metadata(path)?;
+        let modified = metadata.modified()?;
+        Ok(Self {
+            path: String::from(path),
+            size: metadata.len(),
+            checksum: if with_checksum { checksum(path)? } else { String::new() },
+            loaded_at: String::new(),
+            modified: Some(modified),
+        })
+    }
+
+    // a file that was removed counts as changed
+    fn is_unchanged(&self) -> bool {
+        fs::metadata(&self.path).map_or(false, |metadata| {
+            metadata.len() == self.size && metadata.modified().ok() == self.modified
+        })
+    }
+}
+
+fn checksum(path: &str) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+// the directory an --input-files argument lists files from, whose layout is kept when archiving
+fn input_root(arg: &str) -> PathBuf {
+    let path = Path::new(arg);
+    if path.is_dir() {
+        return path.to_path_buf();
+    }
+    if arg.contains(|c| c == '*' || c == '?' || c == '[') {
+        return path
+            .components()
+            .take_while(|c| !c.as_os_str().to_string_lossy().contains(|c| c == '*' || c == '?' || c == '['))
+            .collect();
+    }
+    path.parent().map_or_else(PathBuf::new, Path::to_path_buf)
+}
+
+// renames within a filesystem, copies otherwise, and never replaces an existing file
+fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    if to.exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists", to.display()),
+        ));
+    }
+    if let Some(dir) = to.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    if fs::rename(from, to).is_err() {
+        fs::copy(from, to)?;
+        fs::remove_file(from)?;
+    }
+    Ok(())
+}
+
+// Records loaded input files as NDJSON lines, so that runs over a landing
+// directory skip the files an earlier run loaded.
+#[derive(Clone)]
+pub struct Ledger {
+    path: Option<String>,
+    archive_dir: Option<String>,
+    delete_processed: bool,
+    input_roots: Vec<PathBuf>,
+}
+
+impl Ledger {
+    pub fn new(matches: &ArgMatches) -> Self {
+        let ledger = Self {
+            path: matches.value_of("ledger-file").map(String::from),
+            archive_dir: matches.value_of("archive-dir").map(String::from),
+            delete_processed: matches.is_present("delete-processed"),
+            input_roots: matches.values_of("input-files").map_or(vec![], |args| args.map(input_root).collect()),
+        };
+        if ledger.archive_dir.is_some() && ledger.delete_processed {
+            panic!("archive-dir and delete-processed can't be used together");
+        }
+        ledger
+    }
+
+    pub fn cmd_args() -> CmdArg {
+        CmdArg::new(vec![
+            CmdArgEntry::new(
+                "ledger-file",
+                "Skip input files recorded in this file and record the files loaded",
+                "ledger-file",
+                true,
+                ArgRequired::False(DefaultValue::None),
+            ),
+            CmdArgEntry::new(
+                "archive-dir",
+                "Move input files into this directory once they are loaded",
+                "archive-dir",
+                true,
+                ArgRequired::False(DefaultValue::None),
+            ),
+            CmdArgEntry::new(
+                "delete-processed",
+                "Delete input files once they are loaded",
+                "delete-processed",
+                false,
+                ArgRequired::False(DefaultValue::Bool(false)),
+            ),
+        ])
+    }
+
+    fn entries(&self, ledger_path: &str) -> io::Result<HashMap<String, Entry>> {
+        let file = match File::open(ledger_path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(HashMap::new()),
+            Err(e) => return Err(e),
+        };
+        let mut entries = HashMap::new();
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry: Entry = serde_json::from_str(&line)?;
+            entries.insert(entry.path.clone(), entry);
+        }
+        Ok(entries)
+    }
+
+    // the files whose path, size and checksum don't match a ledger entry
+    pub fn unprocessed(&self, paths: Vec<String>) -> io::Result<Vec<String>> {
+        let ledger_path = match &self.path {
+            Some(ledger_path) => ledger_path,
+            None => return Ok(paths),
+        };
+        let entries = self.entries(ledger_path)?;
+        let mut unprocessed = vec![];
+        for path in paths {
+            let loaded = match entries.get(&path) {
+                Some(entry) if path != "-" && fs::metadata(&path)?.len() == entry.size => {
+                    checksum(&path)? == entry.checksum
+                }
+                _ => false,
+            };
+            if !loaded {
+                unprocessed.push(path);
+            }
+        }
+        Ok(unprocessed)
+    }
+
+    // taken before a file is read, the checksum only if there is a ledger to record it in
+    pub fn entry(&self, path: &str) -> io::Result<Entry> {
+        Entry::of_file(path, self.path.is_some())
+    }
+
+    // the archive path of a file, relative to the input directory it was found in
+    fn archive_path(&self, archive_dir: &str, path: &Path) -> PathBuf {
+        let relative = self
+            .input_roots
+            .iter()
+            .filter_map(|root| path.strip_prefix(root).ok())
+            .min_by_key(|relative| relative.components().count())
+            .unwrap_or_else(|| Path::new(path.file_name().unwrap()));
+        Path::new(archive_dir).join(relative)
+    }
+
+    // Called after the loader has loaded the files. A file that changed since it was
+    // read is left in place and not recorded, so that it is loaded again.
+    pub fn commit(&self, entries: &[Entry]) -> io::Result<()> {
+        let mut unchanged = vec![];
+        for entry in entries.iter().filter(|entry| entry.path != "-") {
+            if entry.is_unchanged() {
+                unchanged.push(entry);
+            } else {
+                eprintln!("{} changed while it was loaded, so it is not recorded", entry.path);
+            }
+        }
+        if let Some(ledger_path) = &self.path {
+            let mut ledger = OpenOptions::new().create(true).append(true).open(ledger_path)?;
+            for entry in &unchanged {
+                let entry = Entry {
+                    loaded_at: Utc::now().to_rfc3339(),
+                    ..(*entry).clone()
+                };
+                let mut line = serde_json::to_vec(&entry)?;
+                line.push(b'\n');
+                ledger.write_all(&line)?;
+            }
+            ledger.sync_all()?;
+        }
+        for entry in &unchanged {
+            if let Some(archive_dir) = &self.archive_dir {
+                let from = Path::new(&entry.path);
+                move_file(from, &self.archive_path(archive_dir, from))?;
+            } else if self.delete_processed {
+                fs::remove_file(&entry.path)?;
+            }
+        }
+        Ok(())
+    }
+}
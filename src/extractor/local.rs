@@ -1,26 +1,223 @@
-use crate::cli::{ArgRequired, CmdArg, CmdArgEntry};
+use crate::cli::{ArgRequired, CmdArg, CmdArgEntry, DefaultValue};
 use crate::error::GenericError;
-use crate::reader;
+use crate::extractor::encoding::InputEncoding;
+use crate::extractor::ledger::{Entry, Ledger};
+use crate::extractor::metadata::MetadataColumns;
+use crate::reader::{self, Input};
+use crate::schema::save_schema;
+use crate::util::{merge_schemas, parse_duration, Tee};
 use arrow::record_batch::RecordBatch;
+use bzip2::read::MultiBzDecoder;
 use clap::ArgMatches;
+use flate2::read::MultiGzDecoder;
 use futures::channel::mpsc;
+use futures::executor::{block_on, block_on_stream};
+use futures::future;
 use futures::stream::{self, StreamExt};
 use futures::SinkExt;
-use std::{fs::File, io, io::Read};
+use notify::event::{AccessKind, AccessMode, ModifyKind, RenameMode};
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use std::collections::{HashSet, VecDeque};
+use std::{fs, fs::File, io, io::{Read, Seek, SeekFrom}, path::Path};
 use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tokio::sync::watch;
+use xz2::read::XzDecoder;
+
+// the compression format told by the leading bytes of a file
+fn compression_of_head(head: &[u8]) -> &'static str {
+    match head {
+        [0x1f, 0x8b, ..] => "gzip",
+        [0x28, 0xb5, 0x2f, 0xfd, ..] => "zstd",
+        // followed by the block size, 1 to 9
+        [b'B', b'Z', b'h', b'1'..=b'9', ..] => "bzip2",
+        [0xfd, 0x37, 0x7a, 0x58, 0x5a, 0x00, ..] => "xz",
+        _ => "none",
+    }
+}
+
+fn compression_of_extension(path: &str) -> Option<&'static str> {
+    let extension = path.rsplit_once('.')?.1;
+    match extension.to_lowercase().as_str() {
+        "gz" | "gzip" => Some("gzip"),
+        "zst" | "zstd" => Some("zstd"),
+        "bz2" => Some("bzip2"),
+        "xz" => Some("xz"),
+        _ => None,
+    }
+}
+
+fn decompress(file: Box<dyn Read + Send>, compression: &str) -> io::Result<Box<dyn Read + Send>> {
+    match compression {
+        "none" => Ok(file),
+        "gzip" => Ok(Box::new(MultiGzDecoder::new(file))),
+        "zstd" => Ok(Box::new(zstd::stream::read::Decoder::new(file)?)),
+        "bzip2" => Ok(Box::new(MultiBzDecoder::new(file))),
+        "xz" => Ok(Box::new(XzDecoder::new_multi_decoder(file))),
+        unknown => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("unknown input compression: {}", unknown),
+        )),
+    }
+}
+
+fn list_dir(dir: &Path, recursive: bool, files: &mut Vec<String>) -> io::Result<()> {
+    let mut entries = fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            if recursive {
+                list_dir(&path, recursive, files)?;
+            }
+        } else {
+            files.push(path.to_string_lossy().into_owned());
+        }
+    }
+    Ok(())
+}
+
+// Expands directories and glob patterns into files, sorted within each argument.
+fn expand_paths(args: Vec<&str>, recursive: bool) -> Result<Vec<String>, GenericError> {
+    if args.len() > 1 && args.contains(&"-") {
+        return Err(GenericError {
+            message: String::from("'-' (stdin) can't be mixed with file paths"),
+        });
+    }
+    let mut paths: Vec<String> = vec![];
+    for arg in args {
+        let mut expanded = vec![];
+        if arg == "-" || Path::new(arg).is_file() {
+            expanded.push(String::from(arg));
+        } else if Path::new(arg).is_dir() {
+            list_dir(Path::new(arg), recursive, &mut expanded)
+                .map_err(|e| GenericError { message: format!("{}: {}", arg, e) })?;
+        } else if arg.contains(|c| c == '*' || c == '?' || c == '[') {
+            let matches = glob::glob(arg)
+                .map_err(|e| GenericError { message: format!("{}: {}", arg, e) })?;
+            for path in matches {
+                let path = path.map_err(|e| GenericError { message: e.to_string() })?;
+                if path.is_file() {
+                    expanded.push(path.to_string_lossy().into_owned());
+                }
+            }
+            if expanded.is_empty() {
+                return Err(GenericError { message: format!("{}: no input files", arg) });
+            }
+            expanded.sort();
+        } else {
+            return Err(GenericError { message: format!("{}: no such file or directory", arg) });
+        }
+        for path in expanded {
+            if !paths.contains(&path) {
+                paths.push(path);
+            }
+        }
+    }
+    Ok(paths)
+}
+
+// a file is complete once its writer closes it or it is moved into the directory
+fn is_completed(kind: &EventKind) -> bool {
+    matches!(
+        kind,
+        EventKind::Access(AccessKind::Close(AccessMode::Write))
+            | EventKind::Modify(ModifyKind::Name(RenameMode::To))
+    )
+}
+
+// size and modification time, which tell a file that was rewritten from the one loaded
+fn file_state(path: &str) -> Option<(u64, SystemTime)> {
+    let metadata = fs::metadata(path).ok().filter(|m| m.is_file())?;
+    Some((metadata.len(), metadata.modified().ok()?))
+}
+
+// options of `erwl watch`
+pub struct WatchOptions {
+    pattern: glob::Pattern,
+    settle_time: Duration,
+}
+
+impl WatchOptions {
+    pub fn new(matches: &ArgMatches) -> Self {
+        let pattern = matches.value_of("watch-pattern").unwrap();
+        Self {
+            pattern: glob::Pattern::new(pattern)
+                .unwrap_or_else(|e| panic!("invalid watch-pattern {}: {}", pattern, e)),
+            settle_time: parse_duration(matches.value_of("watch-settle-time").unwrap())
+                .expect("invalid watch-settle-time"),
+        }
+    }
+
+    pub fn cmd_args() -> CmdArg {
+        CmdArg::new(vec![
+            CmdArgEntry::new(
+                "watch-pattern",
+                "Only load files whose name matches this pattern (e.g. '*.csv')",
+                "watch-pattern",
+                true,
+                ArgRequired::False(DefaultValue::String(String::from("*"))),
+            ),
+            CmdArgEntry::new(
+                "watch-settle-time",
+                "Load a file found at startup once its size and mtime are unchanged for this long",
+                "watch-settle-time",
+                true,
+                ArgRequired::False(DefaultValue::String(String::from("2s"))),
+            ),
+        ])
+    }
+
+    fn matches(&self, path: &str) -> bool {
+        Path::new(path)
+            .file_name()
+            .map_or(false, |name| self.pattern.matches(&name.to_string_lossy()))
+    }
+}
 
 pub struct Extractor {
     file_paths: Vec<String>,
+    input_dirs: Vec<String>,
+    recursive: bool,
     reader: reader::Reader,
+    input_compression: String,
+    input_encoding: InputEncoding,
+    read_parallelism: usize,
+    preserve_order: bool,
+    metadata: MetadataColumns,
+    ledger: Ledger,
+    // files sent to the loader completely, an interrupted run records only these
+    extracted: Arc<Mutex<Vec<Entry>>>,
 }
 
 impl Extractor {
     pub fn new(matches: &ArgMatches) -> Self {
-        let paths = vec![String::from(matches.value_of("input-files").unwrap())];
+        let inputs: Vec<&str> = matches.values_of("input-files").unwrap().collect();
+        let recursive = matches.is_present("input-recursive");
+        let paths = expand_paths(inputs.clone(), recursive).unwrap_or_else(|e| panic!("{}", e.message));
+        let ledger = Ledger::new(matches);
+        let paths = ledger
+            .unprocessed(paths)
+            .unwrap_or_else(|e| panic!("ledger error: {}", e));
         let rdr = reader::Reader::new(&matches);
         Self {
             file_paths: paths,
+            input_dirs: inputs
+                .into_iter()
+                .filter(|input| Path::new(input).is_dir())
+                .map(String::from)
+                .collect(),
+            recursive,
             reader: rdr,
+            input_compression: String::from(matches.value_of("input-compression").unwrap()),
+            input_encoding: InputEncoding::new(matches),
+            read_parallelism: matches.value_of_t("read-parallelism").unwrap(),
+            preserve_order: matches.value_of_t("preserve-order").unwrap(),
+            metadata: MetadataColumns::new(matches),
+            ledger,
+            extracted: Arc::new(Mutex::new(vec![])),
         }
     }
 
@@ -28,11 +225,42 @@ impl Extractor {
         let mut arg_entries = vec![];
         arg_entries.push(CmdArgEntry::new(
             "input-files",
-            "input file paths or '-' for stdin",
+            "input file paths, directories and glob patterns (e.g. 'data/*/**/*.csv'), or '-' for stdin",
             "input-files",
             true,
             ArgRequired::True,
+        ).multiple());
+        arg_entries.push(CmdArgEntry::new(
+            "input-recursive",
+            "Read files in subdirectories of input directories too",
+            "input-recursive",
+            false,
+            ArgRequired::False(DefaultValue::Bool(false)),
+        ));
+        arg_entries.push(CmdArgEntry::new(
+            "input-compression",
+            "Compression of input files (auto, none, gzip, zstd, bzip2 or xz)",
+            "input-compression",
+            true,
+            ArgRequired::False(DefaultValue::String(String::from("auto"))),
         ));
+        arg_entries.push(CmdArgEntry::new(
+            "read-parallelism",
+            "number of input files read concurrently",
+            "read-parallelism",
+            true,
+            ArgRequired::False(DefaultValue::String(String::from("1"))),
+        ));
+        arg_entries.push(CmdArgEntry::new(
+            "preserve-order",
+            "Load records in input file order when reading files concurrently (true or false)",
+            "preserve-order",
+            true,
+            ArgRequired::False(DefaultValue::String(String::from("true"))),
+        ));
+        arg_entries.extend_from_slice(&InputEncoding::cmd_args().entries());
+        arg_entries.extend_from_slice(&MetadataColumns::cmd_args().entries());
+        arg_entries.extend_from_slice(&Ledger::cmd_args().entries());
         arg_entries.extend_from_slice(&reader::Reader::cmd_args().entries());
         CmdArg::new(arg_entries)
     }
@@ -45,35 +273,429 @@ impl Extractor {
         self.reader.clone()
     }
 
-    fn get_file(path: &str) -> Box<dyn Read + Send> {
-        if path == "-" {
-            Box::new(io::stdin())
+    // "auto" looks at the file extension first, then at the leading bytes
+    fn get_file(
+        path: &str,
+        compression: &str,
+        encoding: &InputEncoding,
+    ) -> Result<Input, GenericError> {
+        let file_error = |e: io::Error| GenericError { message: format!("{}: {}", path, e) };
+        let (file, compression): (Box<dyn Read + Send>, &str) = if path == "-" {
+            let stdin: Box<dyn Read + Send> = Box::new(io::stdin());
+            if compression != "auto" {
+                (stdin, compression)
+            } else {
+                let mut tee = Tee::new(stdin);
+                let mut head = vec![];
+                (&mut tee).take(6).read_to_end(&mut head).map_err(file_error)?;
+                (tee.replay(), compression_of_head(&head))
+            }
         } else {
-            Box::new(File::open(path).unwrap())
+            let mut file = File::open(path).map_err(file_error)?;
+            let compression = match (compression, compression_of_extension(path)) {
+                ("auto", Some(by_extension)) => by_extension,
+                ("auto", None) => {
+                    let mut head = vec![];
+                    (&mut file).take(6).read_to_end(&mut head).map_err(file_error)?;
+                    file.seek(SeekFrom::Start(0)).map_err(file_error)?;
+                    compression_of_head(&head)
+                }
+                (given, _) => given,
+            };
+            // kept as a file, so that Parquet can read it in place
+            if compression == "none" && !encoding.transcodes() {
+                return Ok(Input::File(file));
+            }
+            (Box::new(file), compression)
+        };
+        let file = decompress(file, compression).map_err(file_error)?;
+        Ok(Input::Stream(encoding.decode(file)))
+    }
+
+    // records the extracted files in the ledger and archives them, once they are loaded
+    pub fn commit(&self) -> Result<(), GenericError> {
+        self.ledger
+            .commit(&self.extracted.lock().unwrap())
+            .map_err(|e| GenericError { message: format!("ledger error: {}", e) })
+    }
+
+    // adds the number of rows sent to sent_rows, also when the file fails part way
+    async fn send_file(
+        rdr: &reader::Reader,
+        file: Input,
+        path: &str,
+        metadata: &MetadataColumns,
+        tx: &mut mpsc::UnboundedSender<RecordBatch>,
+        sent_rows: &mut usize,
+    ) -> Result<(), GenericError> {
+        let mut reader_stream = rdr.stream(file, path);
+        while let Some(res) = reader_stream.next().await {
+            let res = res.and_then(|(rec, source_rows)| metadata.append(rec, Some(path), None, &source_rows));
+            if let Ok(rec) = res {
+                let num_rows = rec.num_rows();
+                if let Err(_) = tx.send(rec).await {
+                    return Err(GenericError { message: String::from("mpsc send error") })
+                }
+                *sent_rows += num_rows;
+            } else if let Err(e) = res {
+                return Err(GenericError { message: format!("read stream error: {}: {}", path, e) })
+            }
         }
+        Ok(())
     }
 
-    pub fn extract(&self, mut tx: mpsc::UnboundedSender<RecordBatch>) -> impl Future<Output = Result<(), GenericError>> {
-        let rdr = self.reader();
+    // the ledger entry of a file, taken before it is read
+    fn ledger_entry(ledger: &Ledger, path: &str) -> Result<Entry, GenericError> {
+        ledger
+            .entry(path)
+            .map_err(|e| GenericError { message: format!("ledger error: {}: {}", path, e) })
+    }
+
+    // starts reading a file on the blocking thread pool right away
+    fn spawn_read(
+        rdr: reader::Reader,
+        path: String,
+        compression: String,
+        encoding: InputEncoding,
+        metadata: MetadataColumns,
+        ledger: Ledger,
+        mut send: impl FnMut(RecordBatch) -> Result<(), GenericError> + Send + 'static,
+    ) -> impl Future<Output = Result<Entry, GenericError>> {
+        let handle = tokio::task::spawn_blocking(move || {
+            let entry = Self::ledger_entry(&ledger, &path)?;
+            let file = Self::get_file(&path, &compression, &encoding)?;
+            for res in block_on_stream(rdr.stream(file, &path)) {
+                let rec = res.and_then(|(rec, source_rows)| {
+                    metadata.append(rec, Some(&path), None, &source_rows)
+                });
+                match rec {
+                    Ok(rec) => send(rec)?,
+                    Err(e) => {
+                        return Err(GenericError { message: format!("read stream error: {}: {}", path, e) })
+                    }
+                }
+            }
+            Ok(entry)
+        });
+        async move {
+            handle
+                .await
+                .unwrap_or_else(|e| Err(GenericError { message: format!("read task error: {}", e) }))
+        }
+    }
+
+    // once stop is set, the files being read are sent in full and no other file is read
+    pub fn extract(
+        &self,
+        mut tx: mpsc::UnboundedSender<RecordBatch>,
+        stop: watch::Receiver<bool>,
+    ) -> impl Future<Output = Result<(), GenericError>> {
+        let mut rdr = self.reader();
         let file_paths = self.file_paths();
-        // ToDo: check if len(file_paths) > 1 and file_paths not contain '-'
-        let mut file_paths_stream = stream::iter(file_paths);
+        let compression = self.input_compression.clone();
+        let encoding = self.input_encoding.clone();
+        let read_parallelism = self.read_parallelism;
+        let preserve_order = self.preserve_order;
+        let metadata = self.metadata.clone();
+        let extracted = self.extracted.clone();
+        let ledger = self.ledger.clone();
 
         async move {
+            // stdin can't be reopened, so the sampled stream is kept for extraction
+            let mut stdin_file = None;
+            if rdr.schema().is_none() {
+                let mut schemas = vec![];
+                for path in &file_paths {
+                    match rdr.infer_schema(Self::get_file(path, &compression, &encoding)?) {
+                        Ok((schema, file)) => {
+                            schemas.push(schema);
+                            if path == "-" {
+                                stdin_file = Some(file);
+                            }
+                        }
+                        Err(e) => {
+                            return Err(GenericError { message: format!("schema inference error: {}: {}", path, e) })
+                        }
+                    }
+                }
+                let schema = merge_schemas(schemas);
+                if let Some(output) = rdr.inferred_schema_output() {
+                    if let Err(e) = save_schema(&schema, output) {
+                        return Err(GenericError { message: format!("schema output error: {}", e) })
+                    }
+                }
+                rdr.set_schema(schema);
+            }
+
+            if read_parallelism > 1 && stdin_file.is_none() {
+                if preserve_order {
+                    // a read is started only when a file is pulled from the stream, so the
+                    // file being forwarded is one of the read_parallelism files being read;
+                    // once stop is set no file is pulled and those being read are drained
+                    let mut files = stream::iter(file_paths)
+                        .take_while(|_| future::ready(!*stop.borrow()))
+                        .map(|path| {
+                            let (mut file_tx, file_rx) = mpsc::channel(1);
+                            let read = Self::spawn_read(
+                                rdr.clone(),
+                                path,
+                                compression.clone(),
+                                encoding.clone(),
+                                metadata.clone(),
+                                ledger.clone(),
+                                move |rec| {
+                                    block_on(file_tx.send(rec))
+                                        .map_err(|_| GenericError { message: String::from("mpsc send error") })
+                                },
+                            );
+                            future::ready((file_rx, read))
+                        })
+                        .buffered(read_parallelism);
+                    while let Some((mut file_rx, read)) = files.next().await {
+                        while let Some(rec) = file_rx.next().await {
+                            if let Err(_) = tx.send(rec).await {
+                                return Err(GenericError { message: String::from("mpsc send error") })
+                            }
+                        }
+                        let entry = read.await?;
+                        extracted.lock().unwrap().push(entry);
+                    }
+                } else {
+                    let mut reads = stream::iter(file_paths)
+                        .take_while(|_| future::ready(!*stop.borrow()))
+                        .map(|path| {
+                            let file_tx = tx.clone();
+                            let read = Self::spawn_read(
+                                rdr.clone(),
+                                path,
+                                compression.clone(),
+                                encoding.clone(),
+                                metadata.clone(),
+                                ledger.clone(),
+                                move |rec| {
+                                    file_tx.unbounded_send(rec)
+                                        .map_err(|_| GenericError { message: String::from("mpsc send error") })
+                                },
+                            );
+                            read
+                        })
+                        .buffer_unordered(read_parallelism);
+                    while let Some(entry) = reads.next().await {
+                        extracted.lock().unwrap().push(entry?);
+                    }
+                }
+                if let Err(e) = rdr.finish() {
+                    return Err(GenericError { message: format!("read stream error: {}", e) })
+                }
+                return Ok(());
+            }
+
+            let mut file_paths_stream = stream::iter(file_paths);
             while let Some(path) = file_paths_stream.next().await {
-                let file = Self::get_file(&path);
-                let mut reader_stream = rdr.stream(file);
-                while let Some(res) = reader_stream.next().await {
-                    if let Ok(rec) = res {
-                        if let Err(_) = tx.send(rec).await {
-                            return Err(GenericError { message: String::from("mpsc send error") })
+                if *stop.borrow() {
+                    break;
+                }
+                let entry = Self::ledger_entry(&ledger, &path)?;
+                let file = match stdin_file.take() {
+                    Some(file) if path == "-" => file,
+                    other => {
+                        stdin_file = other;
+                        Self::get_file(&path, &compression, &encoding)?
+                    }
+                };
+                Self::send_file(&rdr, file, &path, &metadata, &mut tx, &mut 0).await?;
+                extracted.lock().unwrap().push(entry);
+            }
+            if let Err(e) = rdr.finish() {
+                return Err(GenericError { message: format!("read stream error: {}", e) })
+            }
+            Ok(())
+        }
+    }
+
+    // records the pending files whose last row is within the rows loaded so far
+    fn commit_loaded(
+        ledger: &Ledger,
+        pending: &mut VecDeque<(Entry, usize)>,
+        loaded_rows: usize,
+    ) -> Result<(), GenericError> {
+        while pending.front().map_or(false, |(_, last_row)| *last_row <= loaded_rows) {
+            let (entry, _) = pending.pop_front().unwrap();
+            ledger
+                .commit(&[entry])
+                .map_err(|e| GenericError { message: format!("ledger error: {}", e) })?;
+        }
+        Ok(())
+    }
+
+    // Loads the files in the input directories that aren't in the ledger, then each
+    // new file as it is completed, until the watcher fails or stop is set. Each file
+    // is recorded in the ledger once loaded_rows, the number of rows the loader has
+    // written out, covers its last row.
+    pub fn watch(
+        &self,
+        options: WatchOptions,
+        mut tx: mpsc::UnboundedSender<RecordBatch>,
+        mut loaded_rows: watch::Receiver<usize>,
+        mut stop: watch::Receiver<bool>,
+    ) -> impl Future<Output = Result<(), GenericError>> {
+        let rdr = self.reader();
+        let input_dirs = self.input_dirs.clone();
+        let recursive = self.recursive;
+        let compression = self.input_compression.clone();
+        let encoding = self.input_encoding.clone();
+        let metadata = self.metadata.clone();
+        let ledger = self.ledger.clone();
+
+        async move {
+            if rdr.schema().is_none() {
+                return Err(GenericError { message: String::from("watch needs --schema") })
+            }
+            if input_dirs.is_empty() {
+                return Err(GenericError { message: String::from("watch needs a directory in --input-files") })
+            }
+            let watch_error = |e: notify::Error| GenericError { message: format!("watch error: {}", e) };
+            let (path_tx, mut path_rx) = mpsc::unbounded();
+            let event_tx = path_tx.clone();
+            let mut watcher = notify::recommended_watcher(move |res: notify::Result<Event>| {
+                if let Ok(event) = res {
+                    if is_completed(&event.kind) {
+                        for path in event.paths {
+                            let _ = event_tx.unbounded_send(path.to_string_lossy().into_owned());
                         }
-                    } else {
-                        return Err(GenericError { message: String::from("read stream error") })
                     }
                 }
+            })
+            .map_err(watch_error)?;
+            let mode = if recursive { RecursiveMode::Recursive } else { RecursiveMode::NonRecursive };
+            for dir in &input_dirs {
+                watcher.watch(Path::new(dir), mode).map_err(watch_error)?;
+            }
+            // Listed after the watcher starts, so that no file is missed in between. A file
+            // that is still being written is left to the event of its writer closing it.
+            let existing = expand_paths(input_dirs.iter().map(String::as_str).collect(), recursive)?;
+            let states: Vec<_> = existing.into_iter().map(|path| (file_state(&path), path)).collect();
+            let settle_time = options.settle_time;
+            let settled_tx = path_tx.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(settle_time).await;
+                for (state, path) in states {
+                    if state.is_some() && file_state(&path) == state {
+                        let _ = settled_tx.unbounded_send(path);
+                    }
+                }
+            });
+
+            let ledger_error = |e: io::Error| GenericError { message: format!("ledger error: {}", e) };
+            let mut loaded = HashSet::new();
+            let mut sent_rows = 0;
+            // files sent to the loader, with the number of rows sent up to their last row
+            let mut pending: VecDeque<(Entry, usize)> = VecDeque::new();
+            loop {
+                let path = tokio::select! {
+                    path = path_rx.next() => match path {
+                        Some(path) => path,
+                        None => break,
+                    },
+                    changed = loaded_rows.changed(), if !pending.is_empty() => {
+                        if changed.is_err() {
+                            return Err(GenericError { message: String::from("loader stopped") })
+                        }
+                        Self::commit_loaded(&ledger, &mut pending, *loaded_rows.borrow())?;
+                        continue;
+                    }
+                    _ = stop.changed() => break,
+                };
+                if !options.matches(&path) {
+                    continue;
+                }
+                let state = match file_state(&path) {
+                    Some(state) => state,
+                    None => continue,
+                };
+                // a file rewritten under the same name is loaded again
+                if !loaded.insert((path.clone(), state)) {
+                    continue;
+                }
+                if ledger.unprocessed(vec![path.clone()]).map_err(ledger_error)?.is_empty() {
+                    continue;
+                }
+                let opened = Self::ledger_entry(&ledger, &path)
+                    .and_then(|entry| Ok((entry, Self::get_file(&path, &compression, &encoding)?)));
+                let sent = match opened {
+                    Ok((entry, file)) => Self::send_file(&rdr, file, &path, &metadata, &mut tx, &mut sent_rows)
+                        .await
+                        .map(|_| entry),
+                    Err(e) => Err(e),
+                };
+                // a file that can't be read is skipped until it changes, unless the loader is gone
+                let entry = match sent {
+                    Ok(entry) => entry,
+                    Err(e) if tx.is_closed() => return Err(e),
+                    Err(e) => {
+                        eprintln!("skipped {}: {}", path, e.message);
+                        continue;
+                    }
+                };
+                // a file without rows has nothing to wait for
+                if sent_rows <= *loaded_rows.borrow() {
+                    ledger.commit(&[entry]).map_err(ledger_error)?;
+                } else {
+                    pending.push_back((entry, sent_rows));
+                }
+            }
+            // the loader writes out the rest once the channel is closed
+            drop(tx);
+            while !pending.is_empty() && loaded_rows.changed().await.is_ok() {
+                Self::commit_loaded(&ledger, &mut pending, *loaded_rows.borrow())?;
             }
             Ok(())
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use std::io::Write;
+
+    #[test]
+    fn detects_compression_by_magic() {
+        assert_eq!(compression_of_head(&[0x1f, 0x8b, 0x08]), "gzip");
+        assert_eq!(compression_of_head(&[0x28, 0xb5, 0x2f, 0xfd, 0x00]), "zstd");
+        assert_eq!(compression_of_head(b"BZh91AY&SY"), "bzip2");
+        assert_eq!(compression_of_head(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]), "xz");
+        // text that only starts like a bzip2 header
+        assert_eq!(compression_of_head(b"BZh,name\n"), "none");
+        assert_eq!(compression_of_head(&[0x1f]), "none");
+        assert_eq!(compression_of_head(b""), "none");
+    }
+
+    #[test]
+    fn detects_compression_by_extension() {
+        assert_eq!(compression_of_extension("data/a.csv.gz"), Some("gzip"));
+        assert_eq!(compression_of_extension("a.json.ZST"), Some("zstd"));
+        assert_eq!(compression_of_extension("a.bz2"), Some("bzip2"));
+        assert_eq!(compression_of_extension("a.xz"), Some("xz"));
+        assert_eq!(compression_of_extension("a.csv"), None);
+        assert_eq!(compression_of_extension("gz"), None);
+    }
+
+    #[test]
+    fn decompresses_concatenated_gzip_members() {
+        let mut content = vec![];
+        for part in ["a,1\n", "b,2\n"] {
+            let mut encoder = GzEncoder::new(vec![], flate2::Compression::default());
+            encoder.write_all(part.as_bytes()).unwrap();
+            content.extend(encoder.finish().unwrap());
+        }
+        let mut text = String::new();
+        decompress(Box::new(io::Cursor::new(content)), "gzip")
+            .unwrap()
+            .read_to_string(&mut text)
+            .unwrap();
+        assert_eq!(text, "a,1\nb,2\n");
+        assert!(decompress(Box::new(io::empty()), "lzma").is_err());
+    }
+}
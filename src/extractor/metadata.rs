@@ -0,0 +1,107 @@
+use crate::cli::{ArgRequired, CmdArg, CmdArgEntry, DefaultValue};
+use arrow::array::{ArrayRef, Int64Array, StringArray, TimestampMicrosecondArray};
+use arrow::datatypes::{DataType, Field, Schema, TimeUnit};
+use arrow::error::ArrowError;
+use arrow::record_batch::RecordBatch;
+use chrono::Utc;
+use clap::ArgMatches;
+use std::sync::Arc;
+
+const COLUMNS: [&str; 5] = [
+    "_source_file",
+    "_source_row",
+    "_extracted_at",
+    "_run_id",
+    "_source_table",
+];
+
+// Lineage columns appended to every batch, e.g. --metadata-columns _source_file,_run_id
+#[derive(Clone)]
+pub struct MetadataColumns {
+    columns: Vec<String>,
+    extracted_at: i64,
+    run_id: String,
+}
+
+impl MetadataColumns {
+    pub fn new(matches: &ArgMatches) -> Self {
+        let columns: Vec<String> = matches
+            .values_of("metadata-columns")
+            .map_or(vec![], |values| values.flat_map(|v| v.split(',')).map(String::from).collect());
+        for column in &columns {
+            if !COLUMNS.contains(&column.as_str()) {
+                panic!("unknown metadata column: {}", column);
+            }
+        }
+        let now = Utc::now();
+        Self {
+            columns,
+            extracted_at: now.timestamp_micros(),
+            run_id: matches.value_of("run-id").map_or_else(
+                || format!("{}-{}", now.format("%Y%m%dT%H%M%S%.6fZ"), std::process::id()),
+                String::from,
+            ),
+        }
+    }
+
+    pub fn cmd_args() -> CmdArg {
+        CmdArg::new(vec![
+            CmdArgEntry::new(
+                "metadata-columns",
+                "Columns appended to each record (_source_file, _source_row, _extracted_at, _run_id, _source_table)",
+                "metadata-columns",
+                true,
+                ArgRequired::False(DefaultValue::None),
+            )
+            .multiple(),
+            CmdArgEntry::new(
+                "run-id",
+                "Value of the _run_id column, generated from the start time if omitted",
+                "run-id",
+                true,
+                ArgRequired::False(DefaultValue::None),
+            ),
+        ])
+    }
+
+    // source_file and source_table are null if the extractor has none,
+    // source_rows holds the line or row number of each record in its source
+    pub fn append(
+        &self,
+        batch: RecordBatch,
+        source_file: Option<&str>,
+        source_table: Option<&str>,
+        source_rows: &[usize],
+    ) -> Result<RecordBatch, ArrowError> {
+        if self.columns.is_empty() {
+            return Ok(batch);
+        }
+        let num_rows = batch.num_rows();
+        let text = |value: Option<&str>| -> ArrayRef {
+            Arc::new(StringArray::from(vec![value; num_rows]))
+        };
+        let mut fields = batch.schema().fields().clone();
+        let mut columns = batch.columns().to_vec();
+        for name in &self.columns {
+            let (data_type, column): (DataType, ArrayRef) = match name.as_str() {
+                "_source_file" => (DataType::Utf8, text(source_file)),
+                "_source_table" => (DataType::Utf8, text(source_table)),
+                "_run_id" => (DataType::Utf8, text(Some(&self.run_id))),
+                "_source_row" => (
+                    DataType::Int64,
+                    Arc::new(Int64Array::from_iter_values(source_rows.iter().map(|row| *row as i64))),
+                ),
+                _ => (
+                    DataType::Timestamp(TimeUnit::Microsecond, Some(String::from("UTC"))),
+                    Arc::new(TimestampMicrosecondArray::from_opt_vec(
+                        vec![Some(self.extracted_at); num_rows],
+                        Some(String::from("UTC")),
+                    )),
+                ),
+            };
+            fields.push(Field::new(name, data_type, true));
+            columns.push(column);
+        }
+        RecordBatch::try_new(Arc::new(Schema::new(fields)), columns)
+    }
+}
@@ -4,6 +4,7 @@ use crate::cli::CmdArg;
 use crate::cli::CmdArgEntry;
 use crate::cli::DefaultValue;
 use crate::error::GenericError;
+use crate::extractor::metadata::MetadataColumns;
 use arrow::record_batch::RecordBatch;
 use arrow::array;
 use arrow::datatypes::Schema;
@@ -20,6 +21,7 @@ use sqlx::TypeInfo;
 use sqlx::types::chrono;
 use std::future::Future;
 use std::sync::Arc;
+use tokio::sync::watch;
 
 fn to_arrow_field(name: &str, pgtype: &str) -> Field {
     match pgtype {
@@ -147,6 +149,7 @@ pub struct Extractor {
     url: String,
     table: String,
     fetch_rows: usize,
+    metadata: MetadataColumns,
 }
 
 impl Extractor {
@@ -166,11 +169,12 @@ impl Extractor {
             url,
             table,
             fetch_rows,
+            metadata: MetadataColumns::new(matches),
         }
     }
 
     pub fn cmd_args() -> CmdArg {
-        CmdArg::new(vec![
+        let mut arg_entries = vec![
             CmdArgEntry::new(
                 "table",
                 "PostgreSQL table for record extraction",
@@ -220,14 +224,27 @@ impl Extractor {
                 true,
                 False(DefaultValue::String(String::from("10000"))),
             ),
-        ])
+        ];
+        arg_entries.extend_from_slice(&MetadataColumns::cmd_args().entries());
+        CmdArg::new(arg_entries)
     }
 
-    pub fn extract(&self, mut tx: mpsc::UnboundedSender<RecordBatch>) -> impl Future<Output = Result<(), GenericError>> {
+    // nothing to record once the loader is done
+    pub fn commit(&self) -> Result<(), GenericError> {
+        Ok(())
+    }
+
+    // once stop is set, no more rows are fetched
+    pub fn extract(
+        &self,
+        mut tx: mpsc::UnboundedSender<RecordBatch>,
+        stop: watch::Receiver<bool>,
+    ) -> impl Future<Output = Result<(), GenericError>> {
         let url = self.url.clone();
         let fetch_rows = self.fetch_rows;
         let from_clause = self.table.clone();
         let select_clause = "*";
+        let metadata = self.metadata.clone();
 
         async move {
             let pool = postgres::PgPoolOptions::new()
@@ -252,12 +269,15 @@ impl Extractor {
 
             // first record batch
             let rec = to_record_batch(&rowv, cols, schema.clone());
+            let mut first_row = 1 + rec.num_rows();
+            let source_rows: Vec<usize> = (1..first_row).collect();
+            let rec = metadata.append(rec, None, Some(&from_clause), &source_rows).unwrap();
             if let Err(_) = tx.send(rec).await {
                 return Err(GenericError { message: String::from("mpsc send error") })
             }
 
             // next batches processing
-            loop {
+            while !*stop.borrow() {
                 let rowv = fetch_forward(fetch_rows, &mut transaction).await;
                 if rowv.len() == 0 {
                     break;
@@ -265,6 +285,9 @@ impl Extractor {
 
                 // next iterations: collect values
                 let rec = to_record_batch(&rowv, cols, schema.clone());
+                let source_rows: Vec<usize> = (first_row..first_row + rec.num_rows()).collect();
+                first_row += rec.num_rows();
+                let rec = metadata.append(rec, None, Some(&from_clause), &source_rows).unwrap();
                 if let Err(_) = tx.send(rec).await {
                     return Err(GenericError { message: String::from("mpsc send error") })
                 }
@@ -1,3 +1,5 @@
+#[cfg(feature = "loader")]
+mod roll;
 #[cfg(feature = "loader-s3")]
 mod s3;
 #[cfg(feature = "loader-s3")]
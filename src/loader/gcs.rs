@@ -1,18 +1,21 @@
 use crate::cli::{ArgRequired, CmdArg, CmdArgEntry};
 //use crate::error::LoadError;
-use crate::util::{BatchReceiver, WriteableCursor};
+use crate::error::GenericError;
+use crate::loader::roll::{Output, Roller};
 use crate::writer;
 use arrow::record_batch::RecordBatch;
 use clap::ArgMatches;
 use futures::channel::mpsc;
+use google_cloud_auth::token_source::TokenSource;
 use google_cloud_auth::{create_token_source, Config};
 use std::future::Future;
+use std::sync::Arc;
+use tokio::sync::watch;
 
 pub struct Loader {
     bucket: String,
     key_prefix: String,
-    writer: writer::Writer,
-    load_size: usize,
+    roller: Roller,
 }
 
 /*
@@ -31,12 +34,10 @@ impl From<reqwest::Error> for LoadError {
 
 impl Loader {
     pub async fn new(matches: &ArgMatches) -> Self {
-        let writer = writer::Writer::new(&matches);
         Self {
             bucket: String::from(matches.value_of("gcs-bucket").unwrap()),
             key_prefix: String::from(matches.value_of("key-prefix").unwrap()),
-            writer,
-            load_size: matches.value_of_t("load-size").unwrap(),
+            roller: Roller::new(matches, writer::Writer::new(&matches)),
         }
     }
 
@@ -60,29 +61,18 @@ impl Loader {
                 ArgRequired::True
             ),
         );
-        arg_entries.push(
-            CmdArgEntry::new(
-                "load-size",
-                "number of records in a batch",
-                "load-size",
-                true,
-                ArgRequired::True,
-            ),
-        );
+        arg_entries.extend_from_slice(&Roller::cmd_args().entries());
         arg_entries.extend_from_slice(&writer::Writer::cmd_args().entries());
         CmdArg::new(arg_entries)
     }
 
-    fn writer(&self) -> writer::Writer {
-        self.writer.clone()
-    }
-
-    pub fn load(&self, rx: mpsc::UnboundedReceiver<RecordBatch>) -> impl Future<Output = Result<(), mpsc::TryRecvError>> {
-        let load_size = self.load_size;
+    pub fn load(
+        &self,
+        rx: mpsc::UnboundedReceiver<RecordBatch>,
+        ack: watch::Sender<usize>,
+    ) -> impl Future<Output = Result<(), GenericError>> {
+        let roller = self.roller.clone();
         let bucket = self.bucket.clone();
-        let file_extension = self.writer.file_extension().clone();
-        let mut rcvr = BatchReceiver::new(rx);
-        let writer = self.writer();
         let key_prefix = self.key_prefix.clone();
         let client = reqwest::Client::new();
 
@@ -92,33 +82,39 @@ impl Loader {
                 audience: None,
                 scopes: Some(&scopes),
             };
-            let ts = create_token_source(config).await.unwrap();
-            let token = ts.token().await.unwrap();
-            for i in 0.. {
-                // receive RecordBatches from rx and aggregate them to single RecordBatch
-                if let Some(rec) = rcvr.receive(load_size).await {
-                    // create WriteableCursor
-                    let cursor = WriteableCursor::default();
-                    // write aggregated RecordBatch to WriteableCursor
-                    writer.write(&cursor, rec);
-                    // load contents of WriteableCursor to target destination
-                    client.post(format!(
-                        "https://storage.googleapis.com/upload/storage/v1/b/{}/o?uploadType=media&name={}{}.{}",
-                        bucket,
-                        key_prefix,
-                        i,
-                        file_extension,
-                    ))
-                    .bearer_auth(&token.access_token)
-                    .header("Content-Type", "application/octet-stream")
-                    .body(cursor.into_inner().unwrap())
-                    .send()
-                    .await.unwrap();
-                } else {
-                    break;
+            let ts: Arc<dyn TokenSource> = Arc::from(
+                create_token_source(config)
+                    .await
+                    .map_err(|e| GenericError { message: format!("GCS auth error: {}", e) })?,
+            );
+            roller.run(rx, ack, &key_prefix, move |output: Output| {
+                let mut url = format!(
+                    "https://storage.googleapis.com/upload/storage/v1/b/{}/o?uploadType=media&name={}",
+                    bucket,
+                    output.name,
+                );
+                if let Some(content_encoding) = output.content_encoding {
+                    url.push_str(&format!("&contentEncoding={}", content_encoding));
                 }
-            }
-            Ok(())
+                let request = client.post(url)
+                    .header("Content-Type", output.content_type)
+                    .body(output.bytes);
+                let ts = ts.clone();
+                async move {
+                    // the token source renews the token once it expires, which a long watch outlives
+                    let token = ts
+                        .token()
+                        .await
+                        .map_err(|e| GenericError { message: format!("GCS auth error: {}", e) })?;
+                    request
+                        .bearer_auth(&token.access_token)
+                        .send()
+                        .await
+                        .and_then(|response| response.error_for_status())
+                        .map(|_| ())
+                        .map_err(|e| GenericError { message: format!("upload error: {}", e) })
+                }
+            }).await
         }
     }
     
@@ -1,32 +1,31 @@
 use arrow::record_batch::RecordBatch;
 use crate::cli::{ArgRequired, CmdArg, CmdArgEntry};
 //use crate::error::LoadError;
-use crate::util::{BatchReceiver, WriteableCursor};
+use crate::error::GenericError;
+use crate::loader::roll::{Output, Roller};
 use crate::writer;
 use clap::ArgMatches;
 use futures::channel::mpsc;
 use std::future::Future;
 //use std::fs::File;
 //use std::io::Write;
-use std::path::{Path, PathBuf};
+use std::path::PathBuf;
 use tokio::fs::File;
 use tokio::io::AsyncWriteExt;
+use tokio::sync::watch;
 
 pub struct Loader {
     path: String,
     file_prefix: String,
-    writer: writer::Writer,
-    load_size: usize,
+    roller: Roller,
 }
 
 impl Loader {
     pub async fn new(matches: &ArgMatches) -> Self {
-        let writer = writer::Writer::new(&matches);
         Self {
             path: String::from(matches.value_of("path").unwrap()),
             file_prefix: String::from(matches.value_of("file-prefix").unwrap()),
-            writer,
-            load_size: matches.value_of_t("load-size").unwrap(),
+            roller: Roller::new(matches, writer::Writer::new(&matches)),
         }
     }
 
@@ -38,56 +37,36 @@ impl Loader {
         arg_entries.push(
             CmdArgEntry::new("file-prefix", "File name prefix", "file-prefix", true, ArgRequired::True)
         );
-        arg_entries.push(
-            CmdArgEntry::new(
-                "load-size",
-                "number of records in a batch",
-                "load-size",
-                true,
-                ArgRequired::True,
-            )
-        );
+        arg_entries.extend_from_slice(&Roller::cmd_args().entries());
         arg_entries.extend_from_slice(&writer::Writer::cmd_args().entries());
         CmdArg::new(arg_entries)
     }
 
-    fn writer(&self) -> writer::Writer {
-        self.writer.clone()
-    }
-
-    fn output_path(index: usize, path: &Path, file_prefix: String, file_extension: String) -> PathBuf {
-        path.join(format!("{}{}.{}", file_prefix, index, file_extension))
+    // written under a temporary name first, so that an interrupted run leaves no partial file
+    async fn write(bytes: Vec<u8>, path: PathBuf) -> Result<(), GenericError> {
+        let mut part_path = path.clone().into_os_string();
+        part_path.push(".part");
+        let write_error = |e: std::io::Error| GenericError {
+            message: format!("{}: {}", path.display(), e),
+        };
+        let mut file = File::create(&part_path).await.map_err(write_error)?;
+        file.write_all(&bytes).await.map_err(write_error)?;
+        file.sync_all().await.map_err(write_error)?;
+        tokio::fs::rename(&part_path, &path).await.map_err(write_error)
     }
 
-    async fn write(cursor: WriteableCursor, path: PathBuf) {
-        let mut file = File::create(path).await.unwrap();
-        file.write_all(&cursor.into_inner().unwrap()).await.unwrap();
-    }
-
-    pub fn load(&self, rx: mpsc::UnboundedReceiver<RecordBatch>) -> impl Future<Output = Result<(), mpsc::TryRecvError>> {
-        let load_size = self.load_size;
-        let mut rcvr = BatchReceiver::new(rx);
-        let writer = self.writer();
-        let path_str = self.path.clone();
+    pub fn load(
+        &self,
+        rx: mpsc::UnboundedReceiver<RecordBatch>,
+        ack: watch::Sender<usize>,
+    ) -> impl Future<Output = Result<(), GenericError>> {
+        let roller = self.roller.clone();
+        let path = PathBuf::from(&self.path);
         let file_prefix = self.file_prefix.clone();
-        let file_extension = self.writer.file_extension().clone();
         async move {
-            let path = Path::new(&path_str);
-            for i in 0.. {
-                // receive RecordBatches from rx and aggregate them to single RecordBatch
-                if let Some(rec) = rcvr.receive(load_size).await {
-                    // create WriteableCursor
-                    let cursor = WriteableCursor::default();
-                    // write aggregated RecordBatch to WriteableCursor
-                    writer.write(&cursor, rec);
-                    // load contents of WriteableCursor to target destination
-                    let path = Self::output_path(i, path.clone(), file_prefix.clone(), file_extension.clone());
-                    Self::write(cursor, path).await;
-                } else {
-                    break;
-                }
-            }
-            Ok(())
+            roller.run(rx, ack, &file_prefix, move |output: Output| {
+                Self::write(output.bytes, path.join(output.name))
+            }).await
         }
     }
 }
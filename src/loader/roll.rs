@@ -0,0 +1,179 @@
+use crate::cli::{ArgRequired, CmdArg, CmdArgEntry, DefaultValue};
+use crate::error::GenericError;
+use crate::schema::{format_schema, schema_file_name, schema_name, OUTPUT_SCHEMA_FORMATS};
+use crate::util::{parse_duration, parse_size, BatchReceiver, WriteableCursor};
+use crate::writer::{self, FileWriter};
+use arrow::record_batch::RecordBatch;
+use clap::ArgMatches;
+use futures::channel::mpsc;
+use serde_json::json;
+use std::future::Future;
+use std::time::Duration;
+use tokio::sync::watch;
+
+// A file produced by Roller, stored by each loader under the loader's path or bucket
+pub struct Output {
+    // the prefix followed by the file name, e.g. "records_0.parquet"
+    pub name: String,
+    pub bytes: Vec<u8>,
+    pub content_type: &'static str,
+    pub content_encoding: Option<&'static str>,
+}
+
+
+// Writes received records into output files, one per load-size rows, or rolled
+// at --target-file-size and --flush-interval.
+#[derive(Clone)]
+pub struct Roller {
+    writer: writer::Writer,
+    load_size: usize,
+    target_file_size: Option<usize>,
+    flush_interval: Option<Duration>,
+    output_schema_format: Option<String>,
+}
+
+impl Roller {
+    pub fn new(matches: &ArgMatches, writer: writer::Writer) -> Self {
+        if let Some(format) = matches.value_of("output-schema-format") {
+            if !OUTPUT_SCHEMA_FORMATS.contains(&format) {
+                panic!("unknown output-schema-format: {}", format);
+            }
+        }
+        Self {
+            writer,
+            load_size: matches.value_of_t("load-size").unwrap(),
+            target_file_size: matches
+                .value_of("target-file-size")
+                .map(|size| parse_size(size).expect("invalid target-file-size")),
+            flush_interval: matches
+                .value_of("flush-interval")
+                .map(|interval| parse_duration(interval).expect("invalid flush-interval")),
+            output_schema_format: matches.value_of("output-schema-format").map(String::from),
+        }
+    }
+
+    pub fn cmd_args() -> CmdArg {
+        CmdArg::new(vec![
+            CmdArgEntry::new(
+                "load-size",
+                "number of records in a batch",
+                "load-size",
+                true,
+                ArgRequired::True,
+            ),
+            CmdArgEntry::new(
+                "target-file-size",
+                "Roll to a new file once a file reaches this size (e.g. 256MB)",
+                "target-file-size",
+                true,
+                ArgRequired::False(DefaultValue::None),
+            ),
+            CmdArgEntry::new(
+                "flush-interval",
+                "Write out buffered records once the oldest has waited this long (e.g. 30s, 5m)",
+                "flush-interval",
+                true,
+                ArgRequired::False(DefaultValue::None),
+            ),
+            CmdArgEntry::new(
+                "output-schema-format",
+                "Also write the output schema (bigquery, arrow, avro or ddl) next to the data",
+                "output-schema-format",
+                true,
+                ArgRequired::False(DefaultValue::None),
+            ),
+        ])
+    }
+
+    // prefix is the loader's file prefix or key prefix, and ack receives the number of
+    // rows stored so far. Once the channel is closed, {prefix}manifest.json lists the
+    // data files written, also when the run was stopped by a signal.
+    pub async fn run<S, F>(
+        self,
+        rx: mpsc::UnboundedReceiver<RecordBatch>,
+        ack: watch::Sender<usize>,
+        prefix: &str,
+        mut store: S,
+    ) -> Result<(), GenericError>
+    where
+        S: FnMut(Output) -> F,
+        F: Future<Output = Result<(), GenericError>>,
+    {
+        let mut rcvr = BatchReceiver::new(rx)
+            .with_flush_interval(self.flush_interval)
+            .with_ack(ack);
+        let mut i = 0;
+        let mut open_rows = 0;
+        let mut manifest = vec![];
+        let mut open_file: Option<(WriteableCursor, Box<dyn FileWriter>)> = None;
+        loop {
+            // receive RecordBatches from rx and aggregate them to single RecordBatch
+            let received = rcvr.receive(self.load_size).await;
+            if let Some(rec) = &received {
+                if i == 0 && open_file.is_none() {
+                    if let Some(schema_format) = &self.output_schema_format {
+                        let (content, extension) =
+                            format_schema(&rec.schema(), schema_format, &schema_name(prefix))
+                                .map_err(|e| GenericError {
+                                    message: format!("output schema error: {}", e),
+                                })?;
+                        let content_type = if extension == "sql" { "text/plain" } else { "application/json" };
+                        store(Output {
+                            name: schema_file_name(prefix, extension),
+                            bytes: content.into_bytes(),
+                            content_type,
+                            content_encoding: None,
+                        })
+                        .await?;
+                    }
+                }
+                if open_file.is_none() {
+                    let cursor = WriteableCursor::default();
+                    let file_writer = self.writer.open(&cursor, rec.schema())?;
+                    open_file = Some((cursor, file_writer));
+                }
+                // write RecordBatch to the WriteableCursor of the current file
+                let (cursor, file_writer) = open_file.as_mut().unwrap();
+                file_writer.write(rec);
+                open_rows += rec.num_rows();
+                // without --target-file-size, each batch of load_size rows is a file
+                if !rcvr.flush_due()
+                    && self.target_file_size.map_or(false, |size| {
+                        cursor.bytes_written() + file_writer.buffered_size() < size
+                    })
+                {
+                    continue;
+                }
+            }
+            // load contents of WriteableCursor to target destination
+            if let Some((cursor, file_writer)) = open_file.take() {
+                file_writer.close();
+                let name = format!("{}{}.{}", prefix, i, self.writer.file_extension());
+                let bytes = cursor.into_inner().unwrap();
+                manifest.push(json!({"name": name, "records": open_rows, "bytes": bytes.len()}));
+                store(Output {
+                    name,
+                    bytes,
+                    content_type: self.writer.content_type(),
+                    content_encoding: self.writer.content_encoding(),
+                })
+                .await?;
+                i += 1;
+                open_rows = 0;
+            }
+            rcvr.flushed();
+            // received is also None when the flush interval elapses
+            if received.is_none() && rcvr.is_finished() {
+                break;
+            }
+        }
+        let manifest = serde_json::to_vec_pretty(&json!({ "files": manifest })).unwrap();
+        store(Output {
+            name: format!("{}manifest.json", prefix),
+            bytes: manifest,
+            content_type: "application/json",
+            content_encoding: None,
+        })
+        .await
+    }
+}
@@ -1,6 +1,6 @@
 use crate::cli::{ArgRequired::True, CmdArg, CmdArgEntry};
-use crate::error::LoadError;
-use crate::util::{BatchReceiver, WriteableCursor};
+use crate::error::{GenericError, LoadError};
+use crate::loader::roll::{Output, Roller};
 use crate::writer;
 use arrow::record_batch::RecordBatch;
 use aws_config::meta::region::RegionProviderChain;
@@ -12,13 +12,13 @@ use aws_sdk_s3::{
 use clap::ArgMatches;
 use futures::channel::mpsc;
 use std::future::Future;
+use tokio::sync::watch;
 
 pub struct Loader {
     config: aws_types::sdk_config::SdkConfig,
     bucket: String,
     key_prefix: String,
-    writer: writer::Writer,
-    load_size: usize,
+    roller: Roller,
 }
 
 impl From<SdkError<PutObjectError>> for LoadError {
@@ -29,15 +29,13 @@ impl From<SdkError<PutObjectError>> for LoadError {
 
 impl Loader {
     pub async fn new(matches: &ArgMatches) -> Self {
-        let writer = writer::Writer::new(&matches);
         let region_provider =
             RegionProviderChain::default_provider().or_else(Region::new("us-east-1"));
         Self {
             config: aws_config::from_env().region(region_provider).load().await,
             bucket: String::from(matches.value_of("s3-bucket").unwrap()),
             key_prefix: String::from(matches.value_of("key-prefix").unwrap()),
-            writer,
-            load_size: matches.value_of_t("load-size").unwrap(),
+            roller: Roller::new(matches, writer::Writer::new(&matches)),
         }
     }
 
@@ -49,30 +47,16 @@ impl Loader {
         arg_entries.push(
             CmdArgEntry::new("key-prefix", "S3 key prefix", "key-prefix", true, True),
         );
-        arg_entries.push(
-            CmdArgEntry::new(
-                "load-size",
-                "number of records in a batch",
-                "load-size",
-                true,
-                True,
-            ),
-        );
+        arg_entries.extend_from_slice(&Roller::cmd_args().entries());
         arg_entries.extend_from_slice(&writer::Writer::cmd_args().entries());
         CmdArg::new(arg_entries)
     }
 
-    fn writer(&self) -> writer::Writer {
-        self.writer.clone()
-    }
-
-    fn key<'a>(suffix: usize, key_prefix: String, file_extension: String) -> String {
-        format!("{}{}.{}", key_prefix, suffix, file_extension)
-    }
-
     pub async fn upload(
         bytes: Vec<u8>,
         key: &str,
+        content_type: &str,
+        content_encoding: Option<&str>,
         client: &Client,
         bucket: &String,
     ) -> Result<(), LoadError> {
@@ -81,42 +65,41 @@ impl Loader {
             .put_object()
             .bucket(bucket)
             .key(key)
+            .content_type(content_type)
+            .set_content_encoding(content_encoding.map(String::from))
             .body(stream)
             .send()
             .await?;
         Ok(())
     }
 
-    pub fn load(&self, rx: mpsc::UnboundedReceiver<RecordBatch>) -> impl Future<Output = Result<(), mpsc::TryRecvError>> {
-        let load_size = self.load_size;
-        let mut rcvr = BatchReceiver::new(rx);
-        let writer = self.writer();
+    pub fn load(
+        &self,
+        rx: mpsc::UnboundedReceiver<RecordBatch>,
+        ack: watch::Sender<usize>,
+    ) -> impl Future<Output = Result<(), GenericError>> {
+        let roller = self.roller.clone();
         let key_prefix = self.key_prefix.clone();
         let client = Client::new(&self.config);
         let bucket = self.bucket.clone();
-        let file_extension = self.writer.file_extension().clone();
 
         async move {
-            for i in 0.. {
-                // receive RecordBatches from rx and aggregate them to single RecordBatch
-                if let Some(rec) = rcvr.receive(load_size).await {
-                    // create WriteableCursor
-                    let cursor = WriteableCursor::default();
-                    // write aggregated RecordBatch to WriteableCursor
-                    writer.write(&cursor, rec);
-                    // load contents of WriteableCursor to target destination
-                    let key = Self::key(i, key_prefix.clone(), file_extension.clone());
+            roller.run(rx, ack, &key_prefix, move |output: Output| {
+                let client = client.clone();
+                let bucket = bucket.clone();
+                async move {
                     Self::upload(
-                        cursor.into_inner().unwrap(),
-                        &key,
+                        output.bytes,
+                        &output.name,
+                        output.content_type,
+                        output.content_encoding,
                         &client,
                         &bucket,
-                    ).await.unwrap();
-                } else {
-                    break;
+                    )
+                    .await
+                    .map_err(|e| GenericError { message: format!("{}: {}", output.name, e) })
                 }
-            }
-            Ok(())
+            }).await
         }
     }
 
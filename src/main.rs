@@ -3,30 +3,145 @@ mod error;
 mod extractor;
 mod loader;
 mod reader;
+mod schema;
 mod util;
 mod writer;
 
-use cli::{arg_parse, command};
+use cli::{arg_parse, command, process_args};
+
+// exit codes of a run stopped by a signal, after the buffered records are loaded
+const EXIT_SIGINT: i32 = 130;
+const EXIT_SIGTERM: i32 = 143;
+// exit code when the buffered records couldn't be loaded within --shutdown-timeout
+const EXIT_SHUTDOWN_TIMEOUT: i32 = 124;
+
+// resolves to the exit code of the first SIGINT or SIGTERM
+async fn shutdown_signal() -> i32 {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut sigterm = signal(SignalKind::terminate()).unwrap();
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => EXIT_SIGINT,
+            _ = sigterm.recv() => EXIT_SIGTERM,
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        tokio::signal::ctrl_c().await.unwrap();
+        EXIT_SIGINT
+    }
+}
 
 #[tokio::main]
 async fn main() {
     let extractor_args = extractor::Extractor::cmd_args();
     let loader_args = loader::Loader::cmd_args();
+    let process_args = process_args();
 
     let mut cmd = command();
     cmd = arg_parse(&extractor_args, cmd);
     cmd = arg_parse(&loader_args, cmd);
+    cmd = arg_parse(&process_args, cmd);
+
+    // `erwl watch` takes the same arguments and keeps loading new input files
+    #[cfg(feature = "extractor-local")]
+    let watch_args = extractor::WatchOptions::cmd_args();
+    #[cfg(feature = "extractor-local")]
+    {
+        let mut watch_cmd = clap::Command::new("watch")
+            .about("Continuously load new files from the input directories");
+        watch_cmd = arg_parse(&extractor_args, watch_cmd);
+        watch_cmd = arg_parse(&loader_args, watch_cmd);
+        watch_cmd = arg_parse(&process_args, watch_cmd);
+        watch_cmd = arg_parse(&watch_args, watch_cmd);
+        cmd = cmd.subcommand_negates_reqs(true).subcommand(watch_cmd);
+    }
 
     let m = cmd.get_matches();
+    let watch_matches = m.subcommand_matches("watch").cloned();
+    let is_watch = watch_matches.is_some();
+    let m = watch_matches.unwrap_or(m);
 
+    let shutdown_timeout = util::parse_duration(m.value_of("shutdown-timeout").unwrap())
+        .expect("invalid shutdown-timeout");
     let extractor = extractor::Extractor::new(&m);
     let loader = loader::Loader::new(&m).await;
 
     let (tx, rx) = futures::channel::mpsc::unbounded();
-    let extract = extractor.extract(tx);
-    let load = loader.load(rx);
+    // set on a signal, so that the extractor stops at an input file boundary
+    let (stop_tx, stop_rx) = tokio::sync::watch::channel(false);
+    // the number of rows the loader has written out, for watch to record loaded files
+    let (ack_tx, ack_rx) = tokio::sync::watch::channel(0);
+    let load = loader.load(rx, ack_tx);
+
+    #[cfg(feature = "extractor-local")]
+    let mut extract_handle = if is_watch {
+        tokio::spawn(extractor.watch(extractor::WatchOptions::new(&m), tx, ack_rx, stop_rx))
+    } else {
+        tokio::spawn(extractor.extract(tx, stop_rx))
+    };
+    #[cfg(not(feature = "extractor-local"))]
+    let mut extract_handle = {
+        drop(ack_rx);
+        tokio::spawn(extractor.extract(tx, stop_rx))
+    };
+    let mut loader_handle = tokio::spawn(load);
 
-    tokio::spawn(extract);
-    let loader_handle = tokio::spawn(load);
-    loader_handle.await.unwrap().unwrap();
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
+    let mut interrupted = None;
+    let mut extracted = None;
+    tokio::select! {
+        res = &mut extract_handle => {
+            extracted = Some(res.unwrap());
+        }
+        code = &mut shutdown => {
+            let _ = stop_tx.send(true);
+            interrupted = Some(code);
+        }
+    }
+    let loaded = match interrupted {
+        None => tokio::select! {
+            res = &mut loader_handle => Some(res),
+            code = &mut shutdown => {
+                let _ = stop_tx.send(true);
+                interrupted = Some(code);
+                None
+            }
+        },
+        Some(_) => None,
+    };
+    // the extractor finishes the input files being read, then the loader drains the
+    // channel, finishes the open file and uploads it
+    let loaded = match loaded {
+        Some(res) => res,
+        None => {
+            let finish = async {
+                if extracted.is_none() {
+                    extracted = Some(extract_handle.await.unwrap());
+                }
+                loader_handle.await
+            };
+            match tokio::time::timeout(shutdown_timeout, finish).await {
+                Ok(res) => res,
+                Err(_) => {
+                    eprintln!("buffered records were not loaded within the shutdown timeout");
+                    std::process::exit(EXIT_SHUTDOWN_TIMEOUT);
+                }
+            }
+        }
+    };
+    loaded.unwrap().unwrap();
+    // an extraction error is raised once what was read before it is loaded
+    if let Some(res) = extracted {
+        res.unwrap();
+    }
+    // watch records each file in the ledger as it goes
+    if !is_watch {
+        extractor.commit().unwrap();
+    }
+    if let Some(code) = interrupted {
+        std::process::exit(code);
+    }
 }
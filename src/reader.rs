@@ -1,8 +1,84 @@
+use arrow::record_batch::RecordBatch;
+#[cfg(any(feature = "reader-parquet", feature = "reader-arrow"))]
+use arrow::{array::new_null_array, compute::cast, datatypes::SchemaRef, error::ArrowError};
+use std::fs::File;
+use std::io::Read;
+
+#[cfg(any(feature = "reader-csv", feature = "reader-json"))]
+mod convert;
+#[cfg(any(feature = "reader-csv", feature = "reader-json"))]
+mod reject;
 #[cfg(feature = "reader-json")]
 mod json;
 #[cfg(feature = "reader-json")]
+mod json_path;
+#[cfg(feature = "reader-json")]
 pub use json::Reader;
 #[cfg(feature = "reader-csv")]
 mod csv;
 #[cfg(feature = "reader-csv")]
-pub use csv::Reader;
+pub use self::csv::Reader;
+#[cfg(feature = "reader-parquet")]
+mod parquet;
+#[cfg(feature = "reader-parquet")]
+pub use self::parquet::Reader;
+#[cfg(feature = "reader-avro")]
+mod avro;
+#[cfg(feature = "reader-avro")]
+pub use avro::Reader;
+#[cfg(feature = "reader-arrow")]
+mod ipc;
+#[cfg(feature = "reader-arrow")]
+pub use ipc::Reader;
+
+// An input handed to a reader: a local file that is neither compressed nor transcoded,
+// which Parquet reads in place, or any other input as a stream of bytes
+pub enum Input {
+    File(File),
+    Stream(Box<dyn Read + Send>),
+}
+
+impl Input {
+    pub fn into_read(self) -> Box<dyn Read + Send> {
+        match self {
+            Input::File(file) => Box::new(file),
+            Input::Stream(stream) => stream,
+        }
+    }
+}
+
+// A batch with the position of each record in its input: the line number for CSV and
+// JSON, where rejected records leave gaps, and the 1-based row number otherwise
+pub type SourceBatch = (RecordBatch, Vec<usize>);
+
+// numbers the records of formats that don't reject any, counting from 1
+#[cfg(any(feature = "reader-avro", feature = "reader-arrow"))]
+fn numbered<I>(batches: I) -> impl Iterator<Item = Result<SourceBatch, arrow::error::ArrowError>>
+where
+    I: Iterator<Item = Result<RecordBatch, arrow::error::ArrowError>>,
+{
+    let mut next_row = 1;
+    batches.map(move |batch| {
+        let batch = batch?;
+        let rows = (next_row..next_row + batch.num_rows()).collect();
+        next_row += batch.num_rows();
+        Ok((batch, rows))
+    })
+}
+
+// Parquet and Arrow batches come with the schema of their file, which is matched to the
+// schema merged from all inputs by column name, so that they can be loaded together
+#[cfg(any(feature = "reader-parquet", feature = "reader-arrow"))]
+fn conform(batch: RecordBatch, schema: &SchemaRef) -> Result<RecordBatch, ArrowError> {
+    let batch_schema = batch.schema();
+    let columns = schema
+        .fields()
+        .iter()
+        .map(|field| match batch_schema.index_of(field.name()) {
+            Ok(i) if batch.column(i).data_type() == field.data_type() => Ok(batch.column(i).clone()),
+            Ok(i) => cast(batch.column(i), field.data_type()),
+            Err(_) => Ok(new_null_array(field.data_type(), batch.num_rows())),
+        })
+        .collect::<Result<Vec<_>, ArrowError>>()?;
+    RecordBatch::try_new(schema.clone(), columns)
+}
@@ -0,0 +1,365 @@
+use crate::cli::{ArgRequired, CmdArg, CmdArgEntry, DefaultValue};
+use crate::reader::{numbered, Input, SourceBatch};
+use crate::schema::from_avro_value;
+use crate::util::Tee;
+use apache_avro::types::Value as AvroValue;
+use apache_avro::Decimal;
+use arrow::array::{
+    make_array, ArrayData, ArrayRef, BinaryArray, BooleanArray, Date32Array, DecimalBuilder,
+    FixedSizeBinaryArray, Float32Array, Float64Array, Int32Array, Int64Array, NullArray,
+    StringArray, StructArray, Time32MillisecondArray, Time64MicrosecondArray,
+    TimestampMicrosecondArray, TimestampMillisecondArray,
+};
+use arrow::buffer::{Buffer, MutableBuffer};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use arrow::error::ArrowError;
+use arrow::record_batch::RecordBatch;
+use clap::ArgMatches;
+use futures::stream::{iter, BoxStream};
+use std::io::Read;
+use std::sync::Arc;
+
+fn avro_error(e: apache_avro::Error) -> ArrowError {
+    ArrowError::ExternalError(Box::new(e))
+}
+
+fn type_error(data_type: &DataType, value: &AvroValue) -> ArrowError {
+    ArrowError::ParseError(format!("can't read Avro value {:?} as {:?}", value, data_type))
+}
+
+fn unwrap_union(value: &AvroValue) -> &AvroValue {
+    match value {
+        AvroValue::Union(_, inner) => unwrap_union(inner),
+        v => v,
+    }
+}
+
+// Avro decimals are big-endian two's complement
+fn decimal_value(decimal: &Decimal) -> Option<i128> {
+    let bytes = Vec::<u8>::try_from(decimal).ok()?;
+    if bytes.len() > 16 {
+        return None;
+    }
+    let fill = if bytes.first().map_or(false, |b| b & 0x80 != 0) { 0xff } else { 0 };
+    let mut buf = [fill; 16];
+    buf[16 - bytes.len()..].copy_from_slice(&bytes);
+    Some(i128::from_be_bytes(buf))
+}
+
+fn values_of<T>(
+    data_type: &DataType,
+    values: &[&AvroValue],
+    get: impl Fn(&AvroValue) -> Option<T>,
+) -> Result<Vec<Option<T>>, ArrowError> {
+    values
+        .iter()
+        .map(|v| match unwrap_union(v) {
+            AvroValue::Null => Ok(None),
+            v => get(v).map(Some).ok_or_else(|| type_error(data_type, v)),
+        })
+        .collect()
+}
+
+fn validity(values: &[&AvroValue]) -> Buffer {
+    values
+        .iter()
+        .map(|v| !matches!(unwrap_union(v), AvroValue::Null))
+        .collect::<MutableBuffer>()
+        .into()
+}
+
+fn record_columns(fields: &[Field], values: &[&AvroValue]) -> Result<Vec<ArrayRef>, ArrowError> {
+    let null = AvroValue::Null;
+    fields
+        .iter()
+        .map(|field| {
+            let field_values = values
+                .iter()
+                .map(|v| match unwrap_union(v) {
+                    AvroValue::Record(entries) => Ok(entries
+                        .iter()
+                        .find(|(name, _)| name == field.name())
+                        .map_or(&null, |(_, value)| value)),
+                    AvroValue::Null => Ok(&null),
+                    v => Err(type_error(&DataType::Struct(fields.to_vec()), v)),
+                })
+                .collect::<Result<Vec<&AvroValue>, ArrowError>>()?;
+            build_array(field.data_type(), &field_values)
+        })
+        .collect()
+}
+
+fn build_array(data_type: &DataType, values: &[&AvroValue]) -> Result<ArrayRef, ArrowError> {
+    let array: ArrayRef = match data_type {
+        DataType::Null => Arc::new(NullArray::new(values.len())),
+        DataType::Boolean => Arc::new(BooleanArray::from(values_of(data_type, values, |v| match v {
+            AvroValue::Boolean(b) => Some(*b),
+            _ => None,
+        })?)),
+        DataType::Int32 => Arc::new(Int32Array::from(values_of(data_type, values, |v| match v {
+            AvroValue::Int(i) => Some(*i),
+            _ => None,
+        })?)),
+        DataType::Int64 => Arc::new(Int64Array::from(values_of(data_type, values, |v| match v {
+            AvroValue::Long(l) => Some(*l),
+            AvroValue::Int(i) => Some(*i as i64),
+            _ => None,
+        })?)),
+        DataType::Float32 => Arc::new(Float32Array::from(values_of(data_type, values, |v| match v {
+            AvroValue::Float(f) => Some(*f),
+            _ => None,
+        })?)),
+        DataType::Float64 => Arc::new(Float64Array::from(values_of(data_type, values, |v| match v {
+            AvroValue::Double(d) => Some(*d),
+            AvroValue::Float(f) => Some(*f as f64),
+            _ => None,
+        })?)),
+        DataType::Utf8 => Arc::new(
+            values_of(data_type, values, |v| match v {
+                AvroValue::String(s) | AvroValue::Enum(_, s) => Some(s.clone()),
+                AvroValue::Uuid(u) => Some(u.to_string()),
+                _ => None,
+            })?
+            .into_iter()
+            .collect::<StringArray>(),
+        ),
+        DataType::Binary => {
+            let bytes = values_of(data_type, values, |v| match v {
+                AvroValue::Bytes(b) | AvroValue::Fixed(_, b) => Some(b.clone()),
+                _ => None,
+            })?;
+            Arc::new(BinaryArray::from_opt_vec(bytes.iter().map(|b| b.as_deref()).collect()))
+        }
+        DataType::FixedSizeBinary(_) => {
+            let bytes = values_of(data_type, values, |v| match v {
+                AvroValue::Fixed(_, b) => Some(b.clone()),
+                _ => None,
+            })?;
+            Arc::new(FixedSizeBinaryArray::try_from_sparse_iter(bytes.into_iter())?)
+        }
+        DataType::Date32 => Arc::new(Date32Array::from(values_of(data_type, values, |v| match v {
+            AvroValue::Date(d) | AvroValue::Int(d) => Some(*d),
+            _ => None,
+        })?)),
+        DataType::Time32(TimeUnit::Millisecond) => Arc::new(Time32MillisecondArray::from(
+            values_of(data_type, values, |v| match v {
+                AvroValue::TimeMillis(t) | AvroValue::Int(t) => Some(*t),
+                _ => None,
+            })?,
+        )),
+        DataType::Time64(TimeUnit::Microsecond) => Arc::new(Time64MicrosecondArray::from(
+            values_of(data_type, values, |v| match v {
+                AvroValue::TimeMicros(t) | AvroValue::Long(t) => Some(*t),
+                _ => None,
+            })?,
+        )),
+        DataType::Timestamp(TimeUnit::Millisecond, tz) => {
+            let timestamps = values_of(data_type, values, |v| match v {
+                AvroValue::TimestampMillis(t) | AvroValue::Long(t) => Some(*t),
+                _ => None,
+            })?;
+            Arc::new(TimestampMillisecondArray::from_opt_vec(timestamps, tz.clone()))
+        }
+        DataType::Timestamp(TimeUnit::Microsecond, tz) => {
+            let timestamps = values_of(data_type, values, |v| match v {
+                AvroValue::TimestampMicros(t) | AvroValue::Long(t) => Some(*t),
+                _ => None,
+            })?;
+            Arc::new(TimestampMicrosecondArray::from_opt_vec(timestamps, tz.clone()))
+        }
+        DataType::Decimal(precision, scale) => {
+            let decimals = values_of(data_type, values, |v| match v {
+                AvroValue::Decimal(d) => decimal_value(d),
+                _ => None,
+            })?;
+            let mut builder = DecimalBuilder::new(decimals.len(), *precision, *scale);
+            for decimal in decimals {
+                match decimal {
+                    Some(d) => builder.append_value(d)?,
+                    None => builder.append_null()?,
+                }
+            }
+            Arc::new(builder.finish())
+        }
+        DataType::Struct(fields) => {
+            let columns = record_columns(fields, values)?;
+            let children = fields.iter().cloned().zip(columns).collect::<Vec<_>>();
+            Arc::new(StructArray::from((children, validity(values))))
+        }
+        DataType::List(item) => {
+            let mut offsets = vec![0i32];
+            let mut items = vec![];
+            for value in values {
+                match unwrap_union(value) {
+                    AvroValue::Array(array) => items.extend(array.iter()),
+                    AvroValue::Null => {}
+                    v => return Err(type_error(data_type, v)),
+                }
+                offsets.push(items.len() as i32);
+            }
+            let child = build_array(item.data_type(), &items)?;
+            let data = ArrayData::builder(data_type.clone())
+                .len(values.len())
+                .add_buffer(Buffer::from_slice_ref(&offsets))
+                .add_child_data(child.data().clone())
+                .null_bit_buffer(Some(validity(values)))
+                .build()?;
+            make_array(data)
+        }
+        DataType::Map(entries, _) => {
+            let entry_fields = match entries.data_type() {
+                DataType::Struct(fields) if fields.len() == 2 => fields,
+                _ => return Err(type_error(data_type, &AvroValue::Null)),
+            };
+            let mut offsets = vec![0i32];
+            let mut keys = vec![];
+            let mut map_values = vec![];
+            for value in values {
+                match unwrap_union(value) {
+                    AvroValue::Map(map) => {
+                        for (k, v) in map {
+                            keys.push(Some(k.as_str()));
+                            map_values.push(v);
+                        }
+                    }
+                    AvroValue::Null => {}
+                    v => return Err(type_error(data_type, v)),
+                }
+                offsets.push(keys.len() as i32);
+            }
+            let keys: ArrayRef = Arc::new(StringArray::from(keys));
+            let map_values = build_array(entry_fields[1].data_type(), &map_values)?;
+            let entries = StructArray::from(vec![
+                (entry_fields[0].clone(), keys),
+                (entry_fields[1].clone(), map_values),
+            ]);
+            let data = ArrayData::builder(data_type.clone())
+                .len(values.len())
+                .add_buffer(Buffer::from_slice_ref(&offsets))
+                .add_child_data(entries.data().clone())
+                .null_bit_buffer(Some(validity(values)))
+                .build()?;
+            make_array(data)
+        }
+        unsupported => {
+            return Err(ArrowError::NotYetImplemented(format!(
+                "reading Avro as {:?}",
+                unsupported
+            )))
+        }
+    };
+    Ok(array)
+}
+
+struct Batches {
+    reader: apache_avro::Reader<'static, Box<dyn Read + Send>>,
+    schema: SchemaRef,
+    batch_size: usize,
+}
+
+impl Batches {
+    fn next_batch(&mut self) -> Result<Option<RecordBatch>, ArrowError> {
+        let mut records = vec![];
+        while records.len() < self.batch_size {
+            match self.reader.next() {
+                Some(record) => records.push(record.map_err(avro_error)?),
+                None => break,
+            }
+        }
+        if records.is_empty() {
+            return Ok(None);
+        }
+        let records: Vec<&AvroValue> = records.iter().collect();
+        let columns = record_columns(self.schema.fields(), &records)?;
+        RecordBatch::try_new(self.schema.clone(), columns).map(Some)
+    }
+}
+
+impl Iterator for Batches {
+    type Item = Result<RecordBatch, ArrowError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_batch().transpose()
+    }
+}
+
+#[derive(Clone)]
+pub struct Reader {
+    batch_size: usize,
+    schema: Option<Schema>,
+    inferred_schema_output: Option<String>,
+}
+
+impl Reader {
+    pub fn new(matches: &ArgMatches) -> Self {
+        Self {
+            batch_size: matches.value_of_t("batch-size").unwrap(),
+            schema: None,
+            inferred_schema_output: matches.value_of("inferred-schema-output").map(String::from),
+        }
+    }
+
+    pub fn cmd_args() -> CmdArg {
+        CmdArg::new(vec![
+            CmdArgEntry::new(
+                "inferred-schema-output",
+                "Write the schema of the input to this file (BigQuery schema)",
+                "inferred-schema-output",
+                true,
+                ArgRequired::False(DefaultValue::None),
+            ),
+            CmdArgEntry::new(
+                "batch-size",
+                "number of records in each files",
+                "batch-size",
+                true,
+                ArgRequired::False(DefaultValue::String(String::from("10000"))),
+            ),
+        ])
+    }
+
+    pub fn schema(&self) -> Option<&Schema> {
+        self.schema.as_ref()
+    }
+
+    pub fn set_schema(&mut self, schema: Schema) {
+        self.schema = Some(schema);
+    }
+
+    pub fn inferred_schema_output(&self) -> Option<&String> {
+        self.inferred_schema_output.as_ref()
+    }
+
+    pub fn finish(&self) -> Result<(), ArrowError> {
+        Ok(())
+    }
+
+    // the schema is taken from the header of the container file
+    pub fn infer_schema(
+        &self,
+        input: Input,
+    ) -> Result<(Schema, Input), ArrowError> {
+        let file = input.into_read();
+        let mut tee = Tee::new(file);
+        let writer_schema = {
+            let reader = apache_avro::Reader::new(&mut tee).map_err(avro_error)?;
+            serde_json::to_value(reader.writer_schema())?
+        };
+        let schema = from_avro_value(&writer_schema)
+            .map_err(|e| ArrowError::SchemaError(e.to_string()))?;
+        Ok((schema, Input::Stream(tee.replay())))
+    }
+
+    pub fn stream(&self, input: Input, _path: &str) -> BoxStream<'static, Result<SourceBatch, ArrowError>> {
+        let file = input.into_read();
+        let schema = Arc::new(self.schema.clone().expect("schema is not set"));
+        match apache_avro::Reader::new(file) {
+            Ok(reader) => Box::pin(iter(numbered(Batches {
+                reader,
+                schema,
+                batch_size: self.batch_size,
+            }))),
+            Err(e) => Box::pin(iter(vec![Err(avro_error(e))])),
+        }
+    }
+}
@@ -0,0 +1,682 @@
+use crate::cli::{ArgRequired, CmdArg, CmdArgEntry, DefaultValue};
+use arrow::array::{
+    make_array, Array, ArrayData, ArrayRef, BooleanArray, Date32Array, Date64Array, DecimalBuilder, Float32Array,
+    Float64Array, Int16Array, Int32Array, Int64Array, Int8Array, StringArray,
+    TimestampMicrosecondArray, TimestampMillisecondArray, TimestampNanosecondArray,
+    ListArray, StructArray, TimestampSecondArray, UInt16Array, UInt32Array, UInt64Array,
+    UInt8Array,
+};
+use arrow::compute::{cast, filter_record_batch};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use arrow::error::ArrowError;
+use arrow::record_batch::RecordBatch;
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, TimeZone};
+use chrono_tz::Tz;
+use clap::ArgMatches;
+use serde_json::Value;
+use std::sync::Arc;
+
+const TIMESTAMP_FORMATS: [&str; 4] = [
+    "%Y-%m-%dT%H:%M:%S%.f",
+    "%Y-%m-%d %H:%M:%S%.f",
+    "%Y-%m-%dT%H:%M",
+    "%Y-%m-%d %H:%M",
+];
+
+const OFFSET_TIMESTAMP_FORMATS: [&str; 3] = [
+    "%Y-%m-%d %H:%M:%S%.f%:z",
+    "%Y-%m-%d %H:%M:%S%.f%z",
+    "%Y-%m-%dT%H:%M:%S%.f%z",
+];
+
+#[derive(Clone)]
+enum Timezone {
+    Fixed(FixedOffset),
+    Named(Tz),
+}
+
+impl Timezone {
+    fn parse(name: &str) -> Option<Self> {
+        if let Ok(tz) = name.parse::<Tz>() {
+            return Some(Timezone::Named(tz));
+        }
+        // fixed offsets like "+09:00" or "-0530"
+        let sign = match name.chars().next()? {
+            '+' => 1,
+            '-' => -1,
+            _ => return None,
+        };
+        let digits: String = name[1..].chars().filter(|c| *c != ':').collect();
+        if digits.len() != 4 {
+            return None;
+        }
+        let hours: i32 = digits[..2].parse().ok()?;
+        let minutes: i32 = digits[2..].parse().ok()?;
+        FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60)).map(Timezone::Fixed)
+    }
+
+    fn to_utc(&self, local: &NaiveDateTime) -> Option<NaiveDateTime> {
+        match self {
+            Timezone::Fixed(offset) => offset
+                .from_local_datetime(local)
+                .single()
+                .map(|dt| dt.naive_utc()),
+            Timezone::Named(tz) => tz
+                .from_local_datetime(local)
+                .earliest()
+                .map(|dt| dt.naive_utc()),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ParseOptions {
+    timestamp_format: Option<String>,
+    date_format: String,
+    timezone: Timezone,
+    target_timezone: Option<String>,
+}
+
+impl ParseOptions {
+    pub fn new(matches: &ArgMatches) -> Self {
+        let timezone = matches.value_of("timezone").unwrap();
+        let target_timezone = matches.value_of("target-timezone");
+        if let Some(tz) = target_timezone {
+            Timezone::parse(tz).unwrap_or_else(|| panic!("unknown target timezone: {}", tz));
+        }
+        Self {
+            timestamp_format: matches.value_of("timestamp-format").map(String::from),
+            date_format: String::from(matches.value_of("date-format").unwrap()),
+            timezone: Timezone::parse(timezone)
+                .unwrap_or_else(|| panic!("unknown timezone: {}", timezone)),
+            target_timezone: target_timezone.map(String::from),
+        }
+    }
+
+    pub fn cmd_args() -> CmdArg {
+        CmdArg::new(vec![
+            CmdArgEntry::new(
+                "timestamp-format",
+                "Format of input timestamps (strftime), RFC 3339 and ISO 8601 like values if omitted",
+                "timestamp-format",
+                true,
+                ArgRequired::False(DefaultValue::None),
+            ),
+            CmdArgEntry::new(
+                "date-format",
+                "Format of input dates (strftime)",
+                "date-format",
+                true,
+                ArgRequired::False(DefaultValue::String(String::from("%Y-%m-%d"))),
+            ),
+            CmdArgEntry::new(
+                "timezone",
+                "Timezone of input timestamps without offset (e.g. Asia/Tokyo, +09:00)",
+                "timezone",
+                true,
+                ArgRequired::False(DefaultValue::String(String::from("UTC"))),
+            ),
+            CmdArgEntry::new(
+                "target-timezone",
+                "Timezone annotated on output timestamps, the schema's (UTC) if omitted",
+                "target-timezone",
+                true,
+                ArgRequired::False(DefaultValue::None),
+            ),
+        ])
+    }
+}
+
+fn is_parsed(data_type: &DataType) -> bool {
+    matches!(
+        data_type,
+        DataType::Date32 | DataType::Date64 | DataType::Timestamp(_, _) | DataType::Decimal(_, _)
+    )
+}
+
+// the type a column is decoded as before parsing, nested columns keep their shape
+fn raw_type(data_type: &DataType) -> DataType {
+    match data_type {
+        t if is_parsed(t) => DataType::Utf8,
+        DataType::Struct(fields) => DataType::Struct(
+            fields
+                .iter()
+                .map(|f| Field::new(f.name(), raw_type(f.data_type()), true))
+                .collect(),
+        ),
+        DataType::List(item) => DataType::List(Box::new(Field::new(
+            item.name(),
+            raw_type(item.data_type()),
+            true,
+        ))),
+        t => t.clone(),
+    }
+}
+
+// JSON numbers can't be decoded into text columns, so they are stringified first.
+// Numbers are parsed as f64, so decimals with more digits must be given as strings.
+fn stringify_numbers(data_type: &DataType, value: &mut Value) {
+    match (data_type, value) {
+        (t, v @ Value::Number(_)) if is_parsed(t) => *v = Value::String(v.to_string()),
+        (DataType::Struct(fields), Value::Object(obj)) => {
+            for field in fields {
+                if let Some(v) = obj.get_mut(field.name()) {
+                    stringify_numbers(field.data_type(), v);
+                }
+            }
+        }
+        (DataType::List(item), Value::Array(values)) => {
+            for v in values {
+                stringify_numbers(item.data_type(), v);
+            }
+        }
+        _ => {}
+    }
+}
+
+// a row of a batch that couldn't be converted
+pub struct BadRow {
+    pub row: usize,
+    pub reason: String,
+}
+
+enum ConvertError {
+    Rows(Vec<BadRow>),
+    Arrow(ArrowError),
+}
+
+impl From<ArrowError> for ConvertError {
+    fn from(err: ArrowError) -> ConvertError {
+        ConvertError::Arrow(err)
+    }
+}
+
+// parses every non-empty value, failing with the rows and values that can't be parsed
+fn parse_values<T>(
+    values: &StringArray,
+    parse: impl Fn(&str) -> Option<T>,
+) -> Result<Vec<Option<T>>, Vec<(usize, String)>> {
+    let mut parsed = Vec::with_capacity(values.len());
+    let mut failures = vec![];
+    for (row, value) in values.iter().enumerate() {
+        match value.map(str::trim).filter(|v| !v.is_empty()) {
+            Some(v) => match parse(v) {
+                Some(p) => parsed.push(Some(p)),
+                None => {
+                    failures.push((row, String::from(v)));
+                    parsed.push(None);
+                }
+            },
+            None => parsed.push(None),
+        }
+    }
+    if failures.is_empty() {
+        Ok(parsed)
+    } else {
+        Err(failures)
+    }
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_lowercase().as_str() {
+        "true" | "t" | "1" => Some(true),
+        "false" | "f" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+fn micros(datetime: &NaiveDateTime) -> i64 {
+    datetime.timestamp() * 1_000_000 + datetime.timestamp_subsec_micros() as i64
+}
+
+pub fn parse_decimal(value: &str, precision: usize, scale: usize) -> Option<i128> {
+    let (mantissa, exponent) = match value.find(|c| c == 'e' || c == 'E') {
+        Some(i) => (&value[..i], value[i + 1..].parse::<i32>().ok()?),
+        None => (value, 0),
+    };
+    let (negative, mantissa) = match mantissa.strip_prefix('-') {
+        Some(m) => (true, m),
+        None => (false, mantissa.strip_prefix('+').unwrap_or(mantissa)),
+    };
+    let (int_part, frac_part) = match mantissa.find('.') {
+        Some(i) => (&mantissa[..i], &mantissa[i + 1..]),
+        None => (mantissa, ""),
+    };
+    if (int_part.is_empty() && frac_part.is_empty())
+        || !int_part.chars().all(|c| c.is_ascii_digit())
+        || !frac_part.chars().all(|c| c.is_ascii_digit())
+    {
+        return None;
+    }
+    let digits = format!("{}{}", int_part, frac_part);
+    let digits = digits.trim_start_matches('0').as_bytes();
+    if digits.is_empty() {
+        return Some(0);
+    }
+    // digits * 10^shift is the unscaled value
+    let shift = exponent as i64 - frac_part.len() as i64 + scale as i64;
+    // anything above 10^38 overflows every decimal precision
+    if shift > 38 {
+        return None;
+    }
+    let (digits, shift) = if shift >= 0 {
+        (digits, shift as usize)
+    } else {
+        // fractional digits beyond the scale are only accepted if they are zeros
+        let keep = digits.len().saturating_sub(shift.unsigned_abs() as usize);
+        if digits[keep..].iter().any(|d| *d != b'0') {
+            return None;
+        }
+        (&digits[..keep], 0)
+    };
+    let mut unscaled: i128 = 0;
+    for d in digits {
+        unscaled = unscaled.checked_mul(10)?.checked_add((d - b'0') as i128)?;
+    }
+    unscaled = unscaled.checked_mul(10i128.checked_pow(shift as u32)?)?;
+    if unscaled >= 10i128.checked_pow(precision as u32)? {
+        return None;
+    }
+    Some(if negative { -unscaled } else { unscaled })
+}
+
+// Readers decode dates, timestamps and decimals as text and the Converter parses
+// them into the target schema, since arrow's readers can't handle all notations.
+#[derive(Clone)]
+pub struct Converter {
+    schema: SchemaRef,
+    raw_schema: SchemaRef,
+    options: ParseOptions,
+}
+
+impl Converter {
+    pub fn new(schema: &Schema, options: &ParseOptions) -> Self {
+        let fields: Vec<Field> = schema
+            .fields()
+            .iter()
+            .map(|f| match (f.data_type(), &options.target_timezone) {
+                (DataType::Timestamp(unit, Some(_)), Some(tz)) => Field::new(
+                    f.name(),
+                    DataType::Timestamp(unit.clone(), Some(tz.clone())),
+                    f.is_nullable(),
+                ),
+                _ => f.clone(),
+            })
+            .collect();
+        let raw_fields = fields
+            .iter()
+            .map(|f| {
+                let nullable = f.is_nullable() || is_parsed(f.data_type());
+                Field::new(f.name(), raw_type(f.data_type()), nullable)
+            })
+            .collect();
+        Self {
+            schema: Arc::new(Schema::new(fields)),
+            raw_schema: Arc::new(Schema::new(raw_fields)),
+            options: options.clone(),
+        }
+    }
+
+    pub fn raw_schema(&self) -> SchemaRef {
+        self.raw_schema.clone()
+    }
+
+    pub fn prepare_json(&self, value: &mut Value) {
+        stringify_numbers(&DataType::Struct(self.schema.fields().clone()), value);
+    }
+
+    fn parse_date(&self, value: &str) -> Option<NaiveDate> {
+        NaiveDate::parse_from_str(value, &self.options.date_format).ok()
+    }
+
+    // microseconds since epoch, in UTC if the column has a timezone
+    fn parse_timestamp(&self, value: &str, has_timezone: bool) -> Option<i64> {
+        let localize = |local: NaiveDateTime| {
+            if has_timezone {
+                self.options.timezone.to_utc(&local)
+            } else {
+                Some(local)
+            }
+        };
+        if let Some(format) = &self.options.timestamp_format {
+            if format.contains("%z") || format.contains("%:z") {
+                let datetime = DateTime::parse_from_str(value, format).ok()?;
+                return Some(micros(&datetime.naive_utc()));
+            }
+            let local = NaiveDateTime::parse_from_str(value, format).ok()?;
+            return localize(local).map(|dt| micros(&dt));
+        }
+        if let Ok(datetime) = DateTime::parse_from_rfc3339(value) {
+            return Some(micros(&datetime.naive_utc()));
+        }
+        for format in OFFSET_TIMESTAMP_FORMATS {
+            if let Ok(datetime) = DateTime::parse_from_str(value, format) {
+                return Some(micros(&datetime.naive_utc()));
+            }
+        }
+        // BigQuery exports timestamps like "2022-01-01 00:00:00 UTC"
+        let (value, utc) = match value.strip_suffix(" UTC").or_else(|| value.strip_suffix('Z')) {
+            Some(v) => (v, true),
+            None => (value, false),
+        };
+        let local = TIMESTAMP_FORMATS
+            .iter()
+            .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+            .or_else(|| self.parse_date(value).and_then(|d| d.and_hms_opt(0, 0, 0)))?;
+        if utc {
+            Some(micros(&local))
+        } else {
+            localize(local).map(|dt| micros(&dt))
+        }
+    }
+
+    fn parse_column(&self, field: &Field, column: &ArrayRef) -> Result<ArrayRef, ConvertError> {
+        let values = match column.as_any().downcast_ref::<StringArray>() {
+            Some(values) => values,
+            None => return Ok(cast(column, field.data_type())?),
+        };
+        let parse_error = |failures: Vec<(usize, String)>| {
+            ConvertError::Rows(
+                failures
+                    .into_iter()
+                    .map(|(row, value)| BadRow {
+                        row,
+                        reason: format!(
+                            "Error while parsing value {} for column {}",
+                            value,
+                            field.name()
+                        ),
+                    })
+                    .collect(),
+            )
+        };
+        let epoch = NaiveDate::from_ymd_opt(1970, 1, 1).unwrap();
+        let days = |v: &str| {
+            self.parse_date(v)
+                .map(|d| d.signed_duration_since(epoch).num_days())
+        };
+        let array: ArrayRef = match field.data_type() {
+            DataType::Boolean => Arc::new(BooleanArray::from(
+                parse_values(values, parse_bool).map_err(parse_error)?,
+            )),
+            DataType::Int8 => Arc::new(Int8Array::from(
+                parse_values(values, |v| v.parse::<i8>().ok()).map_err(parse_error)?,
+            )),
+            DataType::Int16 => Arc::new(Int16Array::from(
+                parse_values(values, |v| v.parse::<i16>().ok()).map_err(parse_error)?,
+            )),
+            DataType::Int32 => Arc::new(Int32Array::from(
+                parse_values(values, |v| v.parse::<i32>().ok()).map_err(parse_error)?,
+            )),
+            DataType::Int64 => Arc::new(Int64Array::from(
+                parse_values(values, |v| v.parse::<i64>().ok()).map_err(parse_error)?,
+            )),
+            DataType::UInt8 => Arc::new(UInt8Array::from(
+                parse_values(values, |v| v.parse::<u8>().ok()).map_err(parse_error)?,
+            )),
+            DataType::UInt16 => Arc::new(UInt16Array::from(
+                parse_values(values, |v| v.parse::<u16>().ok()).map_err(parse_error)?,
+            )),
+            DataType::UInt32 => Arc::new(UInt32Array::from(
+                parse_values(values, |v| v.parse::<u32>().ok()).map_err(parse_error)?,
+            )),
+            DataType::UInt64 => Arc::new(UInt64Array::from(
+                parse_values(values, |v| v.parse::<u64>().ok()).map_err(parse_error)?,
+            )),
+            DataType::Float32 => Arc::new(Float32Array::from(
+                parse_values(values, |v| v.parse::<f32>().ok()).map_err(parse_error)?,
+            )),
+            DataType::Float64 => Arc::new(Float64Array::from(
+                parse_values(values, |v| v.parse::<f64>().ok()).map_err(parse_error)?,
+            )),
+            DataType::Decimal(precision, scale) => {
+                let parsed = parse_values(values, |v| parse_decimal(v, *precision, *scale))
+                    .map_err(parse_error)?;
+                let mut builder = DecimalBuilder::new(parsed.len(), *precision, *scale);
+                for value in parsed {
+                    match value {
+                        Some(v) => builder.append_value(v)?,
+                        None => builder.append_null()?,
+                    }
+                }
+                Arc::new(builder.finish())
+            }
+            DataType::Date32 => Arc::new(Date32Array::from(
+                parse_values(values, |v| days(v).map(|d| d as i32)).map_err(parse_error)?,
+            )),
+            DataType::Date64 => Arc::new(Date64Array::from(
+                parse_values(values, |v| days(v).map(|d| d * 86_400_000)).map_err(parse_error)?,
+            )),
+            DataType::Timestamp(unit, tz) => {
+                let parsed = parse_values(values, |v| self.parse_timestamp(v, tz.is_some()))
+                    .map_err(parse_error)?;
+                match unit {
+                    TimeUnit::Second => Arc::new(TimestampSecondArray::from_opt_vec(
+                        parsed.iter().map(|t| t.map(|t| t.div_euclid(1_000_000))).collect(),
+                        tz.clone(),
+                    )),
+                    TimeUnit::Millisecond => Arc::new(TimestampMillisecondArray::from_opt_vec(
+                        parsed.iter().map(|t| t.map(|t| t.div_euclid(1_000))).collect(),
+                        tz.clone(),
+                    )),
+                    TimeUnit::Microsecond => {
+                        Arc::new(TimestampMicrosecondArray::from_opt_vec(parsed, tz.clone()))
+                    }
+                    TimeUnit::Nanosecond => Arc::new(TimestampNanosecondArray::from_opt_vec(
+                        parsed.iter().map(|t| t.map(|t| t * 1_000)).collect(),
+                        tz.clone(),
+                    )),
+                }
+            }
+            _ => cast(column, field.data_type())?,
+        };
+        Ok(array)
+    }
+
+    // converts a struct's children, whose rows are the struct's rows
+    fn convert_struct(&self, fields: &[Field], column: &ArrayRef) -> Result<ArrayRef, ConvertError> {
+        let array = column.as_any().downcast_ref::<StructArray>().unwrap();
+        let mut children = vec![];
+        let mut bad_rows = vec![];
+        for (field, child) in fields.iter().zip(array.columns()) {
+            match self.convert_column(field, child) {
+                Ok(converted) => children.push(converted.data().clone()),
+                Err(ConvertError::Rows(rows)) => bad_rows.extend(rows),
+                Err(e) => return Err(e),
+            }
+        }
+        if !bad_rows.is_empty() {
+            return Err(ConvertError::Rows(bad_rows));
+        }
+        let data = column.data();
+        let converted = ArrayData::builder(DataType::Struct(fields.to_vec()))
+            .len(data.len())
+            .offset(data.offset())
+            .null_bit_buffer(data.null_buffer().cloned())
+            .child_data(children)
+            .build()?;
+        Ok(make_array(converted))
+    }
+
+    // converts a list's values, mapping bad values back to the rows holding them
+    fn convert_list(&self, item: &Field, column: &ArrayRef) -> Result<ArrayRef, ConvertError> {
+        let array = column.as_any().downcast_ref::<ListArray>().unwrap();
+        let values = match self.convert_column(item, &array.values()) {
+            Ok(values) => values,
+            Err(ConvertError::Rows(rows)) => {
+                let offsets = array.value_offsets();
+                return Err(ConvertError::Rows(
+                    rows.into_iter()
+                        .map(|bad| BadRow {
+                            row: offsets.partition_point(|o| *o as usize <= bad.row) - 1,
+                            reason: bad.reason,
+                        })
+                        .collect(),
+                ));
+            }
+            Err(e) => return Err(e),
+        };
+        let data = column.data();
+        let converted = ArrayData::builder(DataType::List(Box::new(item.clone())))
+            .len(data.len())
+            .offset(data.offset())
+            .null_bit_buffer(data.null_buffer().cloned())
+            .buffers(data.buffers().to_vec())
+            .child_data(vec![values.data().clone()])
+            .build()?;
+        Ok(make_array(converted))
+    }
+
+    fn convert_column(&self, field: &Field, column: &ArrayRef) -> Result<ArrayRef, ConvertError> {
+        match (column.data_type(), field.data_type()) {
+            (DataType::Struct(_), DataType::Struct(fields)) => self.convert_struct(fields, column),
+            (DataType::List(_), DataType::List(item)) => self.convert_list(item, column),
+            // text columns are parsed unless the target is text as well
+            (DataType::Utf8, t) if t != &DataType::Utf8 => self.parse_column(field, column),
+            _ => Ok(column.clone()),
+        }
+    }
+
+    fn try_convert(&self, batch: &RecordBatch) -> Result<RecordBatch, ConvertError> {
+        let batch_schema = batch.schema();
+        let mut fields = vec![];
+        let mut columns = vec![];
+        let mut bad_rows = vec![];
+        for (i, raw_field) in batch_schema.fields().iter().enumerate() {
+            let field = self.schema.field_with_name(raw_field.name())?.clone();
+            match self.convert_column(&field, batch.column(i)) {
+                Ok(column) => columns.push(column),
+                Err(ConvertError::Rows(rows)) => bad_rows.extend(rows),
+                Err(e) => return Err(e),
+            }
+            fields.push(field);
+        }
+        if !bad_rows.is_empty() {
+            return Err(ConvertError::Rows(bad_rows));
+        }
+        Ok(RecordBatch::try_new(Arc::new(Schema::new(fields)), columns)?)
+    }
+
+    // converts the batch without the rows that can't be converted, which are returned
+    pub fn convert(&self, batch: RecordBatch) -> Result<(RecordBatch, Vec<BadRow>), ArrowError> {
+        let mut bad_rows = match self.try_convert(&batch) {
+            Ok(converted) => return Ok((converted, vec![])),
+            Err(ConvertError::Arrow(e)) => return Err(e),
+            Err(ConvertError::Rows(rows)) => rows,
+        };
+        bad_rows.sort_by_key(|b| b.row);
+        bad_rows.dedup_by_key(|b| b.row);
+        let keep: BooleanArray = (0..batch.num_rows())
+            .map(|i| Some(bad_rows.binary_search_by_key(&i, |b| b.row).is_err()))
+            .collect();
+        match self.try_convert(&filter_record_batch(&batch, &keep)?) {
+            Ok(converted) => Ok((converted, bad_rows)),
+            Err(ConvertError::Arrow(e)) => Err(e),
+            Err(ConvertError::Rows(_)) => Err(ArrowError::ParseError(String::from(
+                "rows failed to convert after removing bad rows",
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn converter(timezone: &str) -> Converter {
+        let options = ParseOptions {
+            timestamp_format: None,
+            date_format: String::from("%Y-%m-%d"),
+            timezone: Timezone::parse(timezone).unwrap(),
+            target_timezone: None,
+        };
+        Converter::new(&Schema::empty(), &options)
+    }
+
+    fn utc_micros(y: i32, m: u32, d: u32, h: u32, min: u32) -> Option<i64> {
+        NaiveDate::from_ymd_opt(y, m, d)?.and_hms_opt(h, min, 0).map(|dt| micros(&dt))
+    }
+
+    #[test]
+    fn parses_decimals_at_scale() {
+        assert_eq!(parse_decimal("123.45", 10, 2), Some(12345));
+        assert_eq!(parse_decimal("-1.5", 5, 1), Some(-15));
+        assert_eq!(parse_decimal("+.5", 5, 1), Some(5));
+        assert_eq!(parse_decimal("7", 5, 2), Some(700));
+        assert_eq!(parse_decimal("1.50", 5, 1), Some(15));
+        assert_eq!(parse_decimal("000", 5, 1), Some(0));
+    }
+
+    #[test]
+    fn parses_decimal_exponents() {
+        assert_eq!(parse_decimal("1e3", 10, 2), Some(100000));
+        assert_eq!(parse_decimal("1.5E-1", 5, 2), Some(15));
+        assert_eq!(parse_decimal("0e999999999", 38, 9), Some(0));
+        assert_eq!(parse_decimal("1e999999999", 38, 9), None);
+        assert_eq!(parse_decimal("1e-999999999", 38, 9), None);
+    }
+
+    #[test]
+    fn rejects_decimals_that_dont_fit() {
+        // more fractional digits than the scale are not rounded
+        assert_eq!(parse_decimal("1.25", 5, 1), None);
+        assert_eq!(parse_decimal("12345", 4, 0), None);
+        assert_eq!(parse_decimal("1e38", 38, 0), None);
+    }
+
+    #[test]
+    fn rejects_malformed_decimals() {
+        for value in ["", ".", "-", "abc", "1.2.3", "1e", "1,5", "0x10"] {
+            assert_eq!(parse_decimal(value, 10, 2), None, "{}", value);
+        }
+    }
+
+    #[test]
+    fn parses_timestamps_with_offsets() {
+        let converter = converter("UTC");
+        let expected = utc_micros(2023, 12, 31, 15, 0);
+        for value in [
+            "2024-01-01T00:00:00+09:00",
+            "2024-01-01T00:00:00+0900",
+            "2024-01-01 00:00:00+09:00",
+            "2024-01-01 00:00:00.000+0900",
+        ] {
+            assert_eq!(converter.parse_timestamp(value, true), expected, "{}", value);
+        }
+    }
+
+    #[test]
+    fn parses_utc_timestamps() {
+        let converter = converter("Asia/Tokyo");
+        let expected = utc_micros(2024, 1, 1, 0, 0);
+        assert_eq!(converter.parse_timestamp("2024-01-01 00:00:00 UTC", true), expected);
+        assert_eq!(converter.parse_timestamp("2024-01-01T00:00:00Z", true), expected);
+    }
+
+    #[test]
+    fn localizes_timestamps_without_offset() {
+        let converter = converter("Asia/Tokyo");
+        assert_eq!(
+            converter.parse_timestamp("2024-01-01 09:00:00", true),
+            utc_micros(2024, 1, 1, 0, 0)
+        );
+        assert_eq!(
+            converter.parse_timestamp("2024-01-01T09:00", false),
+            utc_micros(2024, 1, 1, 9, 0)
+        );
+        assert_eq!(
+            converter.parse_timestamp("2024-01-01", true),
+            utc_micros(2023, 12, 31, 15, 0)
+        );
+        assert_eq!(converter.parse_timestamp("not a timestamp", true), None);
+    }
+
+    #[test]
+    fn parses_timezones() {
+        assert!(matches!(Timezone::parse("Asia/Tokyo"), Some(Timezone::Named(_))));
+        assert!(matches!(Timezone::parse("+09:00"), Some(Timezone::Fixed(_))));
+        assert!(matches!(Timezone::parse("-0530"), Some(Timezone::Fixed(_))));
+        assert!(Timezone::parse("Mars/Base").is_none());
+        assert!(Timezone::parse("+9").is_none());
+    }
+}
@@ -1,64 +1,302 @@
 use crate::cli::{
     ArgRequired, CmdArg, CmdArgEntry, DefaultValue,
 };
-use crate::util::get_schema;
+use crate::reader::convert::{Converter, ParseOptions};
+use crate::reader::reject::Rejects;
+use crate::reader::{Input, SourceBatch};
+use crate::schema::get_schema;
+use crate::util::{infer_text_type, sample_lines, single_byte};
 use arrow::{error::ArrowError, record_batch::RecordBatch};
-use arrow::csv;
-use arrow::datatypes::Schema;
+use arrow::array::{ArrayRef, StringArray};
+use arrow::datatypes::{DataType, Field, Schema};
 use clap::ArgMatches;
 use futures::stream::{iter, BoxStream};
-use std::io::Read;
-use std::sync::Arc;
+use std::collections::HashSet;
+use std::io::{BufRead, BufReader, Cursor, Read};
+use std::sync::{Arc, Mutex};
+
+fn csv_error(e: csv::Error) -> ArrowError {
+    ArrowError::CsvError(e.to_string())
+}
+
+fn skip_lines<R: BufRead>(reader: &mut R, num_lines: usize) -> std::io::Result<()> {
+    let mut line = Vec::new();
+    for _ in 0..num_lines {
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+    }
+    Ok(())
+}
+
+// Copies everything read from the input so rejected records can be written verbatim.
+struct Recorder {
+    inner: Box<dyn Read + Send>,
+    buffer: Arc<Mutex<Vec<u8>>>,
+}
+
+impl Read for Recorder {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.buffer.lock().unwrap().extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+}
+
+struct Batches {
+    reader: csv::Reader<Box<dyn Read + Send>>,
+    skip_lines: usize,
+    bounds: Option<(usize, usize)>,
+    row: usize,
+    // index of each column in the records, None for a column missing from the header
+    projection: Vec<Option<usize>>,
+    null_value: Option<String>,
+    raw_schema: Arc<Schema>,
+    batch_size: usize,
+    converter: Converter,
+    rejects: Rejects,
+    recorded: Option<Arc<Mutex<Vec<u8>>>>,
+    recorded_offset: u64,
+}
+
+impl Batches {
+    // the input text from `start` up to the current position of the reader
+    fn raw_record(&self, start: u64) -> String {
+        match &self.recorded {
+            Some(recorded) => {
+                let recorded = recorded.lock().unwrap();
+                let from = ((start - self.recorded_offset) as usize).min(recorded.len());
+                let to = ((self.reader.position().byte() - self.recorded_offset) as usize)
+                    .min(recorded.len());
+                String::from_utf8_lossy(&recorded[from..to])
+                    .trim_end_matches(&['\r', '\n'][..])
+                    .to_string()
+            }
+            None => String::new(),
+        }
+    }
+
+    fn discard_recorded(&mut self) {
+        if let Some(recorded) = &self.recorded {
+            let position = self.reader.position().byte();
+            recorded
+                .lock()
+                .unwrap()
+                .drain(..(position - self.recorded_offset) as usize);
+            self.recorded_offset = position;
+        }
+    }
+
+    // reads up to batch_size records as text along with their line numbers and raw text,
+    // records that can't be parsed as CSV are rejected right away
+    fn next_raw_batch(&mut self) -> Result<Option<(RecordBatch, Vec<(usize, String)>)>, ArrowError> {
+        self.discard_recorded();
+        let (start, end) = self.bounds.unwrap_or((0, usize::MAX));
+        let mut columns: Vec<Vec<Option<String>>> = vec![vec![]; self.projection.len()];
+        let mut records = vec![];
+        let mut record = csv::StringRecord::new();
+        while records.len() < self.batch_size && self.row < end {
+            let position = match self.reader.read_record(&mut record) {
+                Ok(false) => break,
+                Ok(true) => record.position().cloned(),
+                Err(e) => {
+                    if let csv::ErrorKind::Io(_) = e.kind() {
+                        return Err(csv_error(e));
+                    }
+                    self.row += 1;
+                    if self.row > start {
+                        let position = e.position().cloned().unwrap_or_else(csv::Position::new);
+                        let line = self.skip_lines + position.line() as usize;
+                        let raw = self.raw_record(position.byte());
+                        self.rejects.reject(line, &raw, &e.to_string())?;
+                    }
+                    continue;
+                }
+            };
+            self.row += 1;
+            if self.row <= start {
+                continue;
+            }
+            for (column, i) in columns.iter_mut().zip(&self.projection) {
+                let value = i
+                    .and_then(|i| record.get(i))
+                    .filter(|v| self.null_value.as_deref() != Some(*v))
+                    .map(String::from);
+                column.push(value);
+            }
+            let position = position.unwrap_or_else(csv::Position::new);
+            records.push((
+                self.skip_lines + position.line() as usize,
+                self.raw_record(position.byte()),
+            ));
+        }
+        if records.is_empty() {
+            return Ok(None);
+        }
+        let arrays: Vec<ArrayRef> = columns
+            .into_iter()
+            .map(|c| Arc::new(c.into_iter().collect::<StringArray>()) as ArrayRef)
+            .collect();
+        let batch = RecordBatch::try_new(self.raw_schema.clone(), arrays)?;
+        Ok(Some((batch, records)))
+    }
+}
+
+impl Iterator for Batches {
+    type Item = Result<SourceBatch, ArrowError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // every row of a batch may be rejected, but the loader takes an empty batch for the end
+        loop {
+            let (batch, records) = match self.next_raw_batch() {
+                Ok(Some(raw)) => raw,
+                Ok(None) => return None,
+                Err(e) => return Some(Err(e)),
+            };
+            let (batch, bad_rows) = match self.converter.convert(batch) {
+                Ok(converted) => converted,
+                Err(e) => return Some(Err(e)),
+            };
+            let mut bad = HashSet::new();
+            for bad_row in bad_rows {
+                let (line, record) = &records[bad_row.row];
+                if let Err(e) = self.rejects.reject(*line, record, &bad_row.reason) {
+                    return Some(Err(e));
+                }
+                bad.insert(bad_row.row);
+            }
+            if let Err(e) = self.rejects.accept(batch.num_rows()) {
+                return Some(Err(e));
+            }
+            if batch.num_rows() > 0 {
+                let lines = records
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| !bad.contains(i))
+                    .map(|(_, (line, _))| *line)
+                    .collect();
+                return Some(Ok((batch, lines)));
+            }
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct Reader {
     batch_size: usize,
-    schema: Schema,
+    schema: Option<Schema>,
+    infer_schema_records: usize,
+    inferred_schema_output: Option<String>,
     has_header: bool,
     delimiter: Option<u8>,
+    quote: u8,
+    escape: Option<u8>,
+    comment: Option<u8>,
+    null_value: Option<String>,
+    skip_lines: usize,
     bounds: Option<(usize, usize)>,
     projection: Option<Vec<usize>>,
-    datetime_format: Option<String>,
+    columns: Option<Vec<String>>,
+    match_header: bool,
+    parse_options: ParseOptions,
+    rejects: Rejects,
 }
 
 impl Reader {
     pub fn new(matches: &ArgMatches) -> Self {
         let batch_size: usize = matches.value_of_t("batch-size").unwrap();
-        let schema_file_path = String::from(matches.value_of("schema-file").unwrap());
-        let schema = get_schema(schema_file_path).unwrap();
+        let schema_format = matches.value_of("schema-format").unwrap();
+        let schema = matches
+            .value_of("schema-file")
+            .map(|path| get_schema(String::from(path), schema_format).unwrap());
+        let infer_schema_records: usize = matches.value_of_t("infer-schema-records").unwrap();
+        let inferred_schema_output = matches.value_of("inferred-schema-output").map(String::from);
         let has_header = matches.is_present("input-csv-header");
-        let delimiter = if let Some(d) = matches.value_of("input-csv-delimiter") {
-            if d.len() == 1 {
-                Some(d.as_bytes()[0])
-            } else {
-                panic!("delimiter must be one character")
-            }
-        } else {
-            None
-        };
-        let bounds = None;
-        let projection = None;
-        let datetime_format = None;
+        let delimiter = matches
+            .value_of("input-csv-delimiter")
+            .map(|d| single_byte("delimiter", d));
+        let quote = single_byte("quote", matches.value_of("input-csv-quote").unwrap());
+        let escape = matches
+            .value_of("input-csv-escape")
+            .map(|e| single_byte("escape", e));
+        let comment = matches
+            .value_of("input-csv-comment")
+            .map(|c| single_byte("comment", c));
+        let null_value = matches.value_of("input-csv-null").map(String::from);
+        let skip_lines: usize = matches.value_of_t("input-csv-skip-lines").unwrap();
+        let bounds = matches.value_of("input-csv-bounds").map(|b| {
+            let (start, end) = b.split_once(',').expect("bounds must be START,END");
+            (start.trim().parse().unwrap(), end.trim().parse().unwrap())
+        });
+        let projection = matches.value_of("input-csv-projection").map(|p| {
+            p.split(',')
+                .map(|i| i.trim().parse().expect("input-csv-projection takes column indices"))
+                .collect()
+        });
+        let columns = matches.value_of("input-csv-columns").map(|c| {
+            c.split(',')
+                .map(|name| String::from(name.trim()))
+                .collect()
+        });
 
-        Self {
+        let reader = Self {
             batch_size,
             schema,
+            infer_schema_records,
+            inferred_schema_output,
             has_header,
             delimiter,
+            quote,
+            escape,
+            comment,
+            null_value,
+            skip_lines,
             bounds,
             projection,
-            datetime_format,
+            columns,
+            match_header: false,
+            parse_options: ParseOptions::new(matches),
+            rejects: Rejects::new(matches),
+        };
+        // an inferred schema is checked once it is set, when the input is read
+        if let Some(schema) = &reader.schema {
+            if let Err(e) = reader.projection(schema) {
+                panic!("{}", e);
+            }
         }
+        reader
     }
 
     pub fn cmd_args() -> CmdArg {
-        CmdArg::new(vec![
+        let mut arg_entries = vec![
             CmdArgEntry::new(
                 "schema-file",
-                "Schema file (BigQuery schema)",
+                "Schema file, inferred from input if omitted",
                 "schema",
                 true,
-                ArgRequired::True,
+                ArgRequired::False(DefaultValue::None),
+            ),
+            CmdArgEntry::new(
+                "schema-format",
+                "Schema file format (bigquery, arrow, avro, json-schema or ddl)",
+                "schema-format",
+                true,
+                ArgRequired::False(DefaultValue::String(String::from("bigquery"))),
+            ),
+            CmdArgEntry::new(
+                "infer-schema-records",
+                "number of records sampled from each input for schema inference",
+                "infer-schema-records",
+                true,
+                ArgRequired::False(DefaultValue::String(String::from("1000"))),
+            ),
+            CmdArgEntry::new(
+                "inferred-schema-output",
+                "Write the inferred schema to this file (BigQuery schema)",
+                "inferred-schema-output",
+                true,
+                ArgRequired::False(DefaultValue::None),
             ),
             CmdArgEntry::new(
                 "batch-size",
@@ -76,24 +314,219 @@ impl Reader {
             ),
             CmdArgEntry::new(
                 "input-csv-delimiter",
-                "Delimiter of input CSV",
+                "Delimiter of input CSV ('\\t' for TSV)",
                 "input-csv-delimiter",
                 true,
                 ArgRequired::False(DefaultValue::String(String::from(","))),
             ),
-        ])
-    }
-
-    pub fn stream(&self, file: Box<dyn Read + Send>) -> BoxStream<'static, Result<RecordBatch, ArrowError>> {
-        Box::pin(iter(csv::reader::Reader::new(
-            file,
-            Arc::new(self.schema.clone()),
-            self.has_header,
-            self.delimiter.clone(),
-            self.batch_size.clone(),
-            self.bounds.clone(),
-            self.projection.clone(),
-            self.datetime_format.clone(),
-        )))
+            CmdArgEntry::new(
+                "input-csv-quote",
+                "Quote character of input CSV",
+                "input-csv-quote",
+                true,
+                ArgRequired::False(DefaultValue::String(String::from("\""))),
+            ),
+            CmdArgEntry::new(
+                "input-csv-escape",
+                "Escape character of input CSV, quotes are escaped by doubling if omitted",
+                "input-csv-escape",
+                true,
+                ArgRequired::False(DefaultValue::None),
+            ),
+            CmdArgEntry::new(
+                "input-csv-comment",
+                "Lines starting with this character are ignored",
+                "input-csv-comment",
+                true,
+                ArgRequired::False(DefaultValue::None),
+            ),
+            CmdArgEntry::new(
+                "input-csv-null",
+                "String representing null in input CSV (e.g. \\N)",
+                "input-csv-null",
+                true,
+                ArgRequired::False(DefaultValue::None),
+            ),
+            CmdArgEntry::new(
+                "input-csv-skip-lines",
+                "Number of leading lines skipped before the header or first record",
+                "input-csv-skip-lines",
+                true,
+                ArgRequired::False(DefaultValue::String(String::from("0"))),
+            ),
+            CmdArgEntry::new(
+                "input-csv-bounds",
+                "Range of records to read as START,END (0-based, END exclusive)",
+                "input-csv-bounds",
+                true,
+                ArgRequired::False(DefaultValue::None),
+            ),
+            CmdArgEntry::new(
+                "input-csv-projection",
+                "Comma separated indices of columns to read",
+                "input-csv-projection",
+                true,
+                ArgRequired::False(DefaultValue::None),
+            ),
+            CmdArgEntry::new(
+                "input-csv-columns",
+                "Comma separated names of columns to read",
+                "input-csv-columns",
+                true,
+                ArgRequired::False(DefaultValue::None),
+            ),
+        ];
+        arg_entries.extend_from_slice(&ParseOptions::cmd_args().entries());
+        arg_entries.extend_from_slice(&Rejects::cmd_args().entries());
+        CmdArg::new(arg_entries)
+    }
+
+    pub fn schema(&self) -> Option<&Schema> {
+        self.schema.as_ref()
+    }
+
+    // an inferred schema merges the headers of all inputs, whose columns may be in
+    // different orders, so each input is then matched to it by header name
+    pub fn set_schema(&mut self, schema: Schema) {
+        self.schema = Some(schema);
+        self.match_header = self.has_header;
+    }
+
+    pub fn inferred_schema_output(&self) -> Option<&String> {
+        self.inferred_schema_output.as_ref()
+    }
+
+    // fails if the rejected records exceed --max-bad-records
+    pub fn finish(&self) -> Result<(), ArrowError> {
+        self.rejects.finish()
+    }
+
+    fn csv_reader<R: Read>(&self, file: R, flexible: bool) -> csv::Reader<R> {
+        csv::ReaderBuilder::new()
+            .has_headers(self.has_header)
+            .delimiter(self.delimiter.unwrap_or(b','))
+            .quote(self.quote)
+            .double_quote(self.escape.is_none())
+            .escape(self.escape)
+            .comment(self.comment)
+            .flexible(flexible)
+            .from_reader(file)
+    }
+
+    // column indices to read, by name (--input-csv-columns) or index (--input-csv-projection)
+    fn projection(&self, schema: &Schema) -> Result<Vec<usize>, ArrowError> {
+        if let Some(columns) = &self.columns {
+            columns.iter().map(|name| schema.index_of(name)).collect()
+        } else if let Some(projection) = &self.projection {
+            let num_columns = schema.fields().len();
+            match projection.iter().find(|i| **i >= num_columns) {
+                Some(i) => Err(ArrowError::SchemaError(format!(
+                    "input-csv-projection index {} is out of range for {} columns",
+                    i, num_columns
+                ))),
+                None => Ok(projection.clone()),
+            }
+        } else {
+            Ok((0..schema.fields().len()).collect())
+        }
+    }
+
+    pub fn infer_schema(
+        &self,
+        input: Input,
+    ) -> Result<(Schema, Input), ArrowError> {
+        let file = input.into_read();
+        let num_lines =
+            self.skip_lines + self.infer_schema_records + if self.has_header { 1 } else { 0 };
+        let (sample, file) = sample_lines(file, num_lines)?;
+        let mut sample = Cursor::new(sample);
+        skip_lines(&mut sample, self.skip_lines)?;
+        let mut reader = self.csv_reader(sample, true);
+        let names: Vec<String> = if self.has_header {
+            reader.headers().map_err(csv_error)?.iter().map(String::from).collect()
+        } else {
+            vec![]
+        };
+        let mut records = vec![];
+        for record in reader.records() {
+            records.push(record.map_err(csv_error)?);
+        }
+        let num_columns = records
+            .iter()
+            .map(|r| r.len())
+            .max()
+            .unwrap_or(0)
+            .max(names.len());
+        let fields = (0..num_columns)
+            .map(|i| {
+                let values: StringArray = records
+                    .iter()
+                    .map(|r| r.get(i).filter(|v| self.null_value.as_deref() != Some(*v)))
+                    .collect();
+                let name = names
+                    .get(i)
+                    .cloned()
+                    .unwrap_or_else(|| format!("column_{}", i + 1));
+                Field::new(&name, infer_text_type(&values), true)
+            })
+            .collect();
+        Ok((Schema::new(fields), Input::Stream(file)))
+    }
+
+    pub fn stream(&self, input: Input, path: &str) -> BoxStream<'static, Result<SourceBatch, ArrowError>> {
+        let file = input.into_read();
+        let schema = self.schema.as_ref().expect("schema is not set");
+        let projection = match self.projection(schema) {
+            Ok(projection) => projection,
+            Err(e) => return Box::pin(iter(vec![Err(e)])),
+        };
+        let raw_schema = Schema::new(
+            projection
+                .iter()
+                .map(|i| Field::new(schema.field(*i).name(), DataType::Utf8, true))
+                .collect(),
+        );
+        let mut file = BufReader::new(file);
+        if let Err(e) = skip_lines(&mut file, self.skip_lines) {
+            return Box::pin(iter(vec![Err(e.into())]));
+        }
+        let mut file: Box<dyn Read + Send> = Box::new(file);
+        let recorded = if self.rejects.keeps_records() {
+            let buffer = Arc::new(Mutex::new(vec![]));
+            file = Box::new(Recorder {
+                inner: file,
+                buffer: buffer.clone(),
+            });
+            Some(buffer)
+        } else {
+            None
+        };
+        let mut reader = self.csv_reader(file, false);
+        let projection = if self.match_header {
+            let headers = match reader.headers() {
+                Ok(headers) => headers.clone(),
+                Err(e) => return Box::pin(iter(vec![Err(csv_error(e))])),
+            };
+            projection
+                .iter()
+                .map(|i| headers.iter().position(|h| h == schema.field(*i).name()))
+                .collect()
+        } else {
+            projection.into_iter().map(Some).collect()
+        };
+        Box::pin(iter(Batches {
+            reader,
+            skip_lines: self.skip_lines,
+            bounds: self.bounds,
+            row: 0,
+            projection,
+            null_value: self.null_value.clone(),
+            raw_schema: Arc::new(raw_schema),
+            batch_size: self.batch_size,
+            converter: Converter::new(schema, &self.parse_options),
+            rejects: self.rejects.for_source(path),
+            recorded,
+            recorded_offset: 0,
+        }))
     }
 }
@@ -0,0 +1,111 @@
+use crate::cli::{ArgRequired, CmdArg, CmdArgEntry, DefaultValue};
+use crate::reader::{conform, numbered, Input, SourceBatch};
+use crate::util::Tee;
+use arrow::datatypes::Schema;
+use arrow::error::ArrowError;
+use arrow::ipc::reader::{FileReader, StreamReader};
+use arrow::record_batch::RecordBatch;
+use clap::ArgMatches;
+use futures::stream::{iter, BoxStream};
+use std::io::{Cursor, Read};
+use std::sync::Arc;
+
+// IPC files start with this, streams with a schema message
+const FILE_MAGIC: &[u8] = b"ARROW1";
+
+fn is_file_format(file: Box<dyn Read + Send>) -> Result<(bool, Box<dyn Read + Send>), ArrowError> {
+    let mut tee = Tee::new(file);
+    let mut magic = vec![];
+    (&mut tee).take(FILE_MAGIC.len() as u64).read_to_end(&mut magic)?;
+    Ok((magic == FILE_MAGIC, tee.replay()))
+}
+
+// the file format needs random access, so it is read into memory, which also works for stdin
+fn file_reader(mut file: Box<dyn Read + Send>) -> Result<FileReader<Cursor<Vec<u8>>>, ArrowError> {
+    let mut content = vec![];
+    file.read_to_end(&mut content)?;
+    FileReader::try_new(Cursor::new(content), None)
+}
+
+#[derive(Clone)]
+pub struct Reader {
+    schema: Option<Schema>,
+    inferred_schema_output: Option<String>,
+}
+
+impl Reader {
+    pub fn new(matches: &ArgMatches) -> Self {
+        Self {
+            schema: None,
+            inferred_schema_output: matches.value_of("inferred-schema-output").map(String::from),
+        }
+    }
+
+    pub fn cmd_args() -> CmdArg {
+        CmdArg::new(vec![CmdArgEntry::new(
+            "inferred-schema-output",
+            "Write the schema of the input to this file (BigQuery schema)",
+            "inferred-schema-output",
+            true,
+            ArgRequired::False(DefaultValue::None),
+        )])
+    }
+
+    pub fn schema(&self) -> Option<&Schema> {
+        self.schema.as_ref()
+    }
+
+    pub fn set_schema(&mut self, schema: Schema) {
+        self.schema = Some(schema);
+    }
+
+    pub fn inferred_schema_output(&self) -> Option<&String> {
+        self.inferred_schema_output.as_ref()
+    }
+
+    pub fn finish(&self) -> Result<(), ArrowError> {
+        Ok(())
+    }
+
+    // the schema is taken from the file footer or the first message of the stream
+    pub fn infer_schema(
+        &self,
+        input: Input,
+    ) -> Result<(Schema, Input), ArrowError> {
+        let file = input.into_read();
+        let (is_file, file) = is_file_format(file)?;
+        if is_file {
+            let mut content = vec![];
+            let mut file = file;
+            file.read_to_end(&mut content)?;
+            let reader = FileReader::try_new(Cursor::new(&content), None)?;
+            let schema = reader.schema().as_ref().clone();
+            return Ok((schema, Input::Stream(Box::new(Cursor::new(content)))));
+        }
+        let mut tee = Tee::new(file);
+        let schema = StreamReader::try_new(&mut tee)?.schema().as_ref().clone();
+        Ok((schema, Input::Stream(tee.replay())))
+    }
+
+    pub fn stream(&self, input: Input, _path: &str) -> BoxStream<'static, Result<SourceBatch, ArrowError>> {
+        let file = input.into_read();
+        let schema = Arc::new(self.schema.clone().expect("schema is not set"));
+        let batches: Box<dyn Iterator<Item = Result<RecordBatch, ArrowError>> + Send> =
+            match is_file_format(file) {
+                Ok((true, file)) => match file_reader(file) {
+                    Ok(reader) => Box::new(reader),
+                    Err(e) => Box::new(std::iter::once(Err(e))),
+                },
+                Ok((false, file)) => match StreamReader::try_new(file) {
+                    Ok(reader) => Box::new(reader),
+                    Err(e) => Box::new(std::iter::once(Err(e))),
+                },
+                Err(e) => Box::new(std::iter::once(Err(e))),
+            };
+        // the loader takes an empty batch for the end of input
+        let batches = batches
+            .filter(|b| !matches!(b, Ok(b) if b.num_rows() == 0))
+            .map(move |b| conform(b?, &schema));
+        Box::pin(iter(numbered(batches)))
+    }
+}
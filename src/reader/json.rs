@@ -2,41 +2,248 @@ use crate::cli::{
     ArgRequired::{False, True},
     CmdArg, CmdArgEntry, DefaultValue,
 };
-use crate::util::get_schema;
+use crate::reader::convert::{Converter, ParseOptions};
+use crate::reader::json_path::Mapping;
+use crate::reader::reject::Rejects;
+use crate::reader::{Input, SourceBatch};
+use crate::schema::get_schema;
+use crate::util::{is_temporal_candidate, refine_schema, sample_lines, text_schema};
 use arrow::{
-    datatypes::Schema, error::ArrowError, json, json::reader::DecoderOptions,
+    datatypes::Schema,
+    error::ArrowError,
+    json,
+    json::reader::{Decoder, DecoderOptions},
     record_batch::RecordBatch,
 };
 use clap::ArgMatches;
 use futures::stream::{iter, BoxStream};
-use std::io::Read;
+use serde_json::{Map, Value};
+use std::collections::HashSet;
+use std::io::{BufRead, BufReader, Cursor, Lines, Read};
 use std::sync::Arc;
 
+// Expands nested objects into parent_child keys and arrays into JSON strings,
+// for outputs like CSV that can't hold nested values.
+fn flatten(value: Value) -> Value {
+    fn flatten_into(prefix: Option<&str>, obj: Map<String, Value>, flat: &mut Map<String, Value>) {
+        for (key, value) in obj {
+            let key = match prefix {
+                Some(prefix) => format!("{}_{}", prefix, key),
+                None => key,
+            };
+            match value {
+                Value::Object(nested) => flatten_into(Some(&key), nested, flat),
+                Value::Array(_) => {
+                    flat.insert(key, Value::String(value.to_string()));
+                }
+                _ => {
+                    flat.insert(key, value);
+                }
+            }
+        }
+    }
+    match value {
+        Value::Object(obj) => {
+            let mut flat = Map::new();
+            flatten_into(None, obj, &mut flat);
+            Value::Object(flat)
+        }
+        _ => value,
+    }
+}
+
+// applies --json-path-mapping and then --flatten to a record
+fn shape(value: Value, mapping: &Option<Mapping>, flatten_nested: bool) -> Value {
+    let value = match mapping {
+        Some(mapping) => mapping.apply(value),
+        None => value,
+    };
+    if flatten_nested {
+        flatten(value)
+    } else {
+        value
+    }
+}
+
+struct Batches {
+    lines: Lines<BufReader<Box<dyn Read + Send>>>,
+    line: usize,
+    batch_size: usize,
+    flatten: bool,
+    mapping: Option<Mapping>,
+    decoder: Decoder,
+    converter: Converter,
+    rejects: Rejects,
+}
+
+impl Batches {
+    // parses a line into the value handed to the decoder
+    fn parse_value(&self, line: &str) -> serde_json::Result<Value> {
+        let mut value = shape(serde_json::from_str(line)?, &self.mapping, self.flatten);
+        self.converter.prepare_json(&mut value);
+        Ok(value)
+    }
+
+    // reads up to batch_size JSON values along with their line numbers and raw text,
+    // lines that aren't valid JSON are rejected right away
+    fn next_values(&mut self) -> Result<(Vec<Value>, Vec<(usize, String)>), ArrowError> {
+        let mut values = vec![];
+        let mut records = vec![];
+        while values.len() < self.batch_size {
+            let line = match self.lines.next() {
+                Some(line) => line?,
+                None => break,
+            };
+            self.line += 1;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match self.parse_value(&line) {
+                Ok(value) => {
+                    values.push(value);
+                    records.push((self.line, line));
+                }
+                Err(e) => self.rejects.reject(self.line, &line, &e.to_string())?,
+            }
+        }
+        Ok((values, records))
+    }
+
+    fn decode(
+        &mut self,
+        values: Vec<Value>,
+        records: Vec<(usize, String)>,
+    ) -> Result<Option<(RecordBatch, Vec<(usize, String)>)>, ArrowError> {
+        if let Ok(batch) = self.decoder.next_batch(&mut values.into_iter().map(Ok)) {
+            return Ok(batch.map(|b| (b, records)));
+        }
+        // the decoder fails the whole batch on a single bad value, so the values it consumed
+        // are parsed again from their lines and decoded one by one
+        let mut good_values = vec![];
+        let mut good_records = vec![];
+        for (line, record) in records {
+            let value = self.parse_value(&record)?;
+            match self.decoder.next_batch(&mut std::iter::once(Ok(value.clone()))) {
+                Ok(_) => {
+                    good_values.push(value);
+                    good_records.push((line, record));
+                }
+                Err(e) => self.rejects.reject(line, &record, &e.to_string())?,
+            }
+        }
+        let batch = self.decoder.next_batch(&mut good_values.into_iter().map(Ok))?;
+        Ok(batch.map(|b| (b, good_records)))
+    }
+}
+
+impl Iterator for Batches {
+    type Item = Result<SourceBatch, ArrowError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // every row of a batch may be rejected, but the loader takes an empty batch for the end
+        loop {
+            let (values, records) = match self.next_values() {
+                Ok((values, _)) if values.is_empty() => return None,
+                Ok(read) => read,
+                Err(e) => return Some(Err(e)),
+            };
+            let (batch, records) = match self.decode(values, records) {
+                Ok(Some(decoded)) => decoded,
+                Ok(None) => continue,
+                Err(e) => return Some(Err(e)),
+            };
+            let (batch, bad_rows) = match self.converter.convert(batch) {
+                Ok(converted) => converted,
+                Err(e) => return Some(Err(e)),
+            };
+            let mut bad = HashSet::new();
+            for bad_row in bad_rows {
+                let (line, record) = &records[bad_row.row];
+                if let Err(e) = self.rejects.reject(*line, record, &bad_row.reason) {
+                    return Some(Err(e));
+                }
+                bad.insert(bad_row.row);
+            }
+            if let Err(e) = self.rejects.accept(batch.num_rows()) {
+                return Some(Err(e));
+            }
+            if batch.num_rows() > 0 {
+                let lines = records
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| !bad.contains(i))
+                    .map(|(_, (line, _))| *line)
+                    .collect();
+                return Some(Ok((batch, lines)));
+            }
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct Reader {
-    schema: Schema,
+    batch_size: usize,
+    flatten: bool,
+    mapping: Option<Mapping>,
+    schema: Option<Schema>,
+    infer_schema_records: usize,
+    inferred_schema_output: Option<String>,
     decoder_options: DecoderOptions,
+    parse_options: ParseOptions,
+    rejects: Rejects,
 }
 
 impl Reader {
     pub fn new(matches: &ArgMatches) -> Self {
         let batch_size: usize = matches.value_of_t("batch-size").unwrap();
-        let schema_file_path = String::from(matches.value_of("schema-file").unwrap());
+        let schema_format = matches.value_of("schema-format").unwrap();
         Self {
-            schema: get_schema(schema_file_path).unwrap(),
+            batch_size,
+            flatten: matches.is_present("flatten"),
+            mapping: matches.value_of("json-path-mapping").map(|path| {
+                Mapping::from_file(path)
+                    .unwrap_or_else(|e| panic!("invalid json-path-mapping {}: {}", path, e))
+            }),
+            schema: matches
+                .value_of("schema-file")
+                .map(|path| get_schema(String::from(path), schema_format).unwrap()),
+            infer_schema_records: matches.value_of_t("infer-schema-records").unwrap(),
+            inferred_schema_output: matches.value_of("inferred-schema-output").map(String::from),
             decoder_options: DecoderOptions::new().with_batch_size(batch_size),
+            parse_options: ParseOptions::new(matches),
+            rejects: Rejects::new(matches),
         }
     }
 
     pub fn cmd_args() -> CmdArg {
-        CmdArg::new(vec![
+        let mut arg_entries = vec![
             CmdArgEntry::new(
                 "schema-file",
-                "Schema file (BigQuery schema)",
+                "Schema file, inferred from input if omitted",
                 "schema",
                 true,
-                True,
+                False(DefaultValue::None),
+            ),
+            CmdArgEntry::new(
+                "schema-format",
+                "Schema file format (bigquery, arrow, avro, json-schema or ddl)",
+                "schema-format",
+                true,
+                False(DefaultValue::String(String::from("bigquery"))),
+            ),
+            CmdArgEntry::new(
+                "infer-schema-records",
+                "number of records sampled from each input for schema inference",
+                "infer-schema-records",
+                true,
+                False(DefaultValue::String(String::from("1000"))),
+            ),
+            CmdArgEntry::new(
+                "inferred-schema-output",
+                "Write the inferred schema to this file (BigQuery schema)",
+                "inferred-schema-output",
+                true,
+                False(DefaultValue::None),
             ),
             CmdArgEntry::new(
                 "batch-size",
@@ -45,15 +252,105 @@ impl Reader {
                 true,
                 False(DefaultValue::String(String::from("10000"))),
             ),
-        ])
+            CmdArgEntry::new(
+                "flatten",
+                "Expand nested objects into parent_child columns and arrays into JSON strings",
+                "flatten",
+                false,
+                False(DefaultValue::Bool(false)),
+            ),
+            CmdArgEntry::new(
+                "json-path-mapping",
+                "JSON file mapping columns to JSONPath expressions, e.g. {\"user_id\": \"$.payload.user.id\"}",
+                "json-path-mapping",
+                true,
+                False(DefaultValue::None),
+            ),
+        ];
+        arg_entries.extend_from_slice(&ParseOptions::cmd_args().entries());
+        arg_entries.extend_from_slice(&Rejects::cmd_args().entries());
+        CmdArg::new(arg_entries)
+    }
+
+    pub fn schema(&self) -> Option<&Schema> {
+        self.schema.as_ref()
+    }
+
+    pub fn set_schema(&mut self, schema: Schema) {
+        self.schema = Some(schema);
+    }
+
+    pub fn inferred_schema_output(&self) -> Option<&String> {
+        self.inferred_schema_output.as_ref()
+    }
+
+    // fails if the rejected records exceed --max-bad-records
+    pub fn finish(&self) -> Result<(), ArrowError> {
+        self.rejects.finish()
+    }
+
+    pub fn infer_schema(
+        &self,
+        input: Input,
+    ) -> Result<(Schema, Input), ArrowError> {
+        let file = input.into_read();
+        let (sample, file) = sample_lines(file, self.infer_schema_records)?;
+        let sample = if self.flatten || self.mapping.is_some() {
+            let mut shaped = vec![];
+            for line in sample.as_slice().lines() {
+                let line = line?;
+                if let Ok(value) = serde_json::from_str::<Value>(&line) {
+                    serde_json::to_writer(&mut shaped, &shape(value, &self.mapping, self.flatten))?;
+                    shaped.push(b'\n');
+                }
+            }
+            shaped
+        } else {
+            sample
+        };
+        let schema = json::reader::infer_json_schema(
+            &mut BufReader::new(Cursor::new(&sample)),
+            Some(self.infer_schema_records),
+        )?;
+        // only string-like columns are re-read as text, other JSON values can't be
+        let candidates = Schema::new(
+            schema
+                .fields()
+                .iter()
+                .filter(|f| is_temporal_candidate(f.data_type()))
+                .cloned()
+                .collect(),
+        );
+        if candidates.fields().is_empty() {
+            return Ok((schema, Input::Stream(file)));
+        }
+        let mut sample_reader = json::reader::Reader::new(
+            Cursor::new(sample),
+            Arc::new(text_schema(&candidates)),
+            DecoderOptions::new().with_batch_size(self.infer_schema_records.max(1)),
+        );
+        match sample_reader.next()? {
+            Some(batch) => Ok((refine_schema(&schema, &batch), Input::Stream(file))),
+            None => Ok((schema, Input::Stream(file))),
+        }
     }
 
-    pub fn stream(&self, file: Box<dyn Read + Send>) -> BoxStream<'static, Result<RecordBatch, ArrowError>> {
+    pub fn stream(&self, input: Input, path: &str) -> BoxStream<'static, Result<SourceBatch, ArrowError>> {
+        let file = input.into_read();
         // async json reader not supported yet: https://github.com/apache/arrow-rs/issues/78
-        Box::pin(iter(json::reader::Reader::new(
-            file,
-            Arc::new(self.schema.clone()),
-            self.decoder_options.clone(),
-        )))
+        let converter = Converter::new(
+            self.schema.as_ref().expect("schema is not set"),
+            &self.parse_options,
+        );
+        Box::pin(iter(Batches {
+            lines: BufReader::new(file).lines(),
+            line: 0,
+            batch_size: self.batch_size,
+            flatten: self.flatten,
+            mapping: self.mapping.clone(),
+            decoder: Decoder::new(converter.raw_schema(), self.decoder_options.clone()),
+            converter,
+            rejects: self.rejects.for_source(path),
+        }))
     }
 }
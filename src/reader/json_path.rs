@@ -0,0 +1,188 @@
+use crate::error::GenericError;
+use serde_json::{Map, Value};
+use std::fs;
+
+#[derive(Clone)]
+enum Step {
+    Key(String),
+    Index(usize),
+    // [*] selects every element of an array
+    Wildcard,
+}
+
+// A JSONPath subset: $.a.b, $['a'], $.a[0] and $.a[*].b
+#[derive(Clone)]
+pub struct JsonPath {
+    steps: Vec<Step>,
+}
+
+impl JsonPath {
+    pub fn parse(path: &str) -> Result<Self, GenericError> {
+        let error = || GenericError {
+            message: format!("invalid JSONPath: {}", path),
+        };
+        let mut rest = path.trim().strip_prefix('$').ok_or_else(error)?;
+        let mut steps = vec![];
+        while !rest.is_empty() {
+            if let Some(r) = rest.strip_prefix('.') {
+                let end = r.find(|c| c == '.' || c == '[').unwrap_or(r.len());
+                if end == 0 {
+                    return Err(error());
+                }
+                steps.push(Step::Key(String::from(&r[..end])));
+                rest = &r[end..];
+            } else if let Some(r) = rest.strip_prefix('[') {
+                let end = r.find(']').ok_or_else(error)?;
+                let selector = r[..end].trim();
+                let quoted = selector
+                    .strip_prefix('\'')
+                    .and_then(|s| s.strip_suffix('\''))
+                    .or_else(|| selector.strip_prefix('"').and_then(|s| s.strip_suffix('"')));
+                steps.push(match quoted {
+                    Some(key) => Step::Key(String::from(key)),
+                    None if selector == "*" => Step::Wildcard,
+                    None => Step::Index(selector.parse().map_err(|_| error())?),
+                });
+                rest = &r[end + 1..];
+            } else {
+                return Err(error());
+            }
+        }
+        Ok(Self { steps })
+    }
+
+    fn select_steps(steps: &[Step], value: &Value) -> Option<Value> {
+        let (step, rest) = match steps.split_first() {
+            Some(split) => split,
+            None => return Some(value.clone()),
+        };
+        match (step, value) {
+            (Step::Key(key), Value::Object(obj)) => Self::select_steps(rest, obj.get(key)?),
+            (Step::Index(i), Value::Array(values)) => Self::select_steps(rest, values.get(*i)?),
+            (Step::Wildcard, Value::Array(values)) => Some(Value::Array(
+                values
+                    .iter()
+                    .filter_map(|v| Self::select_steps(rest, v))
+                    .collect(),
+            )),
+            _ => None,
+        }
+    }
+
+    // the selected value, None if the path doesn't exist or is null
+    pub fn select(&self, value: &Value) -> Option<Value> {
+        Self::select_steps(&self.steps, value).filter(|v| !v.is_null())
+    }
+}
+
+#[derive(Clone)]
+struct ColumnMapping {
+    column: String,
+    path: JsonPath,
+    default: Option<Value>,
+}
+
+// Maps columns to JSONPath expressions, read from a JSON file like
+// {"user_id": "$.payload.user.id", "country": {"path": "$.geo.country", "default": "JP"}}
+#[derive(Clone)]
+pub struct Mapping {
+    columns: Vec<ColumnMapping>,
+}
+
+impl Mapping {
+    pub fn from_file(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    fn parse(content: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let definitions: Map<String, Value> = serde_json::from_str(content)?;
+        let mut columns = vec![];
+        for (column, definition) in definitions {
+            let (path, default) = match &definition {
+                Value::String(path) => (path.as_str(), None),
+                Value::Object(obj) => match obj.get("path") {
+                    Some(Value::String(path)) => (path.as_str(), obj.get("default").cloned()),
+                    _ => {
+                        return Err(Box::new(GenericError {
+                            message: format!("no \"path\" for column {}", column),
+                        }))
+                    }
+                },
+                _ => {
+                    return Err(Box::new(GenericError {
+                        message: format!("invalid mapping for column {}", column),
+                    }))
+                }
+            };
+            columns.push(ColumnMapping {
+                column,
+                path: JsonPath::parse(path)?,
+                default,
+            });
+        }
+        Ok(Self { columns })
+    }
+
+    // sets each mapped column on the record, other columns are read by name as usual
+    pub fn apply(&self, value: Value) -> Value {
+        let mut record = match &value {
+            Value::Object(obj) => obj.clone(),
+            _ => Map::new(),
+        };
+        for mapping in &self.columns {
+            match mapping.path.select(&value).or_else(|| mapping.default.clone()) {
+                Some(v) => record.insert(mapping.column.clone(), v),
+                None => record.remove(&mapping.column),
+            };
+        }
+        Value::Object(record)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn selects_paths() {
+        let value = json!({"a": {"b": [{"c": 1}, {"c": 2}, {"d": 3}], "k.e.y": "x"}});
+        let select = |path: &str| JsonPath::parse(path).unwrap().select(&value);
+        assert_eq!(select("$.a.b[1].c"), Some(json!(2)));
+        assert_eq!(select("$['a'][\"k.e.y\"]"), Some(json!("x")));
+        assert_eq!(select("$.a.b[*].c"), Some(json!([1, 2])));
+        assert_eq!(select("$.a.b[5]"), None);
+        assert_eq!(select("$.a.missing"), None);
+        assert_eq!(select("$"), Some(value.clone()));
+    }
+
+    #[test]
+    fn rejects_invalid_paths() {
+        for path in ["", "a.b", "$.", "$.a..b", "$[0", "$[x]", "$a"] {
+            assert!(JsonPath::parse(path).is_err(), "{}", path);
+        }
+    }
+
+    #[test]
+    fn applies_mappings() {
+        let mapping = Mapping::parse(
+            r#"{"user_id": "$.payload.user.id", "country": {"path": "$.geo.country", "default": "JP"}}"#,
+        )
+        .unwrap();
+        let record = json!({"payload": {"user": {"id": 7}}, "geo": {"country": null}, "other": true});
+        assert_eq!(
+            mapping.apply(record),
+            json!({"payload": {"user": {"id": 7}}, "geo": {"country": null}, "other": true, "user_id": 7, "country": "JP"})
+        );
+        // a column whose path is missing is unset, even if the record has it
+        assert_eq!(mapping.apply(json!({"user_id": 1})), json!({"country": "JP"}));
+    }
+
+    #[test]
+    fn rejects_invalid_mappings() {
+        assert!(Mapping::parse(r#"{"a": 1}"#).is_err());
+        assert!(Mapping::parse(r#"{"a": {"default": 1}}"#).is_err());
+        assert!(Mapping::parse(r#"{"a": "a.b"}"#).is_err());
+        assert!(Mapping::parse(r#"["$.a"]"#).is_err());
+    }
+}
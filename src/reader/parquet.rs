@@ -0,0 +1,477 @@
+use crate::cli::{ArgRequired, CmdArg, CmdArgEntry, DefaultValue};
+use crate::reader::{conform, Input, SourceBatch};
+use arrow::datatypes::{DataType, Schema};
+use arrow::error::ArrowError;
+use clap::ArgMatches;
+use futures::stream::{iter, BoxStream};
+use parquet::arrow::{ArrowReader, ParquetFileArrowReader, ProjectionMask};
+use parquet::errors::ParquetError;
+use parquet::file::metadata::{ParquetMetaData, RowGroupMetaData};
+use parquet::file::reader::{FileReader, RowGroupReader};
+use parquet::file::serialized_reader::SerializedFileReader;
+use parquet::file::statistics::Statistics;
+use parquet::record::reader::RowIter;
+use parquet::schema::types::Type as SchemaType;
+use parquet::util::cursor::SliceableCursor;
+use std::cmp::Ordering;
+use std::io::Read;
+use std::sync::Arc;
+
+fn parquet_error(e: ParquetError) -> ArrowError {
+    ArrowError::ParquetError(e.to_string())
+}
+
+// Parquet needs random access, so a local file is read in place and other input, such
+// as stdin, is read into memory. Also returns an input that reads the same file again.
+fn open(input: Input) -> Result<(Arc<dyn FileReader>, Input), ArrowError> {
+    match input {
+        Input::File(file) => {
+            let file_reader = SerializedFileReader::new(file.try_clone()?).map_err(parquet_error)?;
+            Ok((Arc::new(file_reader), Input::File(file)))
+        }
+        Input::Stream(mut stream) => {
+            let mut content = vec![];
+            stream.read_to_end(&mut content)?;
+            let content = SliceableCursor::new(content);
+            let file_reader = SerializedFileReader::new(content.clone()).map_err(parquet_error)?;
+            Ok((Arc::new(file_reader), Input::Stream(Box::new(content))))
+        }
+    }
+}
+
+// The selected row groups of a file, reusing the metadata read when it was opened
+struct RowGroups {
+    file_reader: Arc<dyn FileReader>,
+    selected: Vec<usize>,
+    metadata: ParquetMetaData,
+}
+
+impl RowGroups {
+    fn new(file_reader: Arc<dyn FileReader>, selected: Vec<usize>) -> Self {
+        let metadata = file_reader.metadata();
+        let metadata = ParquetMetaData::new(
+            metadata.file_metadata().clone(),
+            selected.iter().map(|i| metadata.row_group(*i).clone()).collect(),
+        );
+        Self {
+            file_reader,
+            selected,
+            metadata,
+        }
+    }
+}
+
+impl FileReader for RowGroups {
+    fn metadata(&self) -> &ParquetMetaData {
+        &self.metadata
+    }
+
+    fn num_row_groups(&self) -> usize {
+        self.selected.len()
+    }
+
+    fn get_row_group(&self, i: usize) -> parquet::errors::Result<Box<dyn RowGroupReader + '_>> {
+        self.file_reader.get_row_group(self.selected[i])
+    }
+
+    fn get_row_iter(&self, projection: Option<SchemaType>) -> parquet::errors::Result<RowIter> {
+        RowIter::from_file(projection, self)
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Clone)]
+struct Predicate {
+    column: String,
+    op: Op,
+    value: String,
+}
+
+impl Predicate {
+    fn parse(predicate: &str) -> Self {
+        let ops = [
+            ("!=", Op::Ne),
+            ("<=", Op::Le),
+            (">=", Op::Ge),
+            ("=", Op::Eq),
+            ("<", Op::Lt),
+            (">", Op::Gt),
+        ];
+        for (token, op) in ops {
+            if let Some((column, value)) = predicate.split_once(token) {
+                if column.trim().is_empty() {
+                    break;
+                }
+                return Self {
+                    column: String::from(column.trim()),
+                    op,
+                    value: String::from(value.trim()),
+                };
+            }
+        }
+        panic!("invalid row group filter: {}", predicate)
+    }
+
+    // compares the min/max statistics with the value, None if they can't be compared
+    fn compare(&self, statistics: &Statistics, data_type: &DataType) -> Option<(Ordering, Ordering)> {
+        if !statistics.has_min_max_set() {
+            return None;
+        }
+        let value = self.value.as_str();
+        match (statistics, data_type) {
+            (Statistics::Boolean(s), DataType::Boolean) => {
+                let v: bool = value.parse().ok()?;
+                Some((s.min().cmp(&v), s.max().cmp(&v)))
+            }
+            (Statistics::Int32(s), DataType::Int8 | DataType::Int16 | DataType::Int32) => {
+                let v: i32 = value.parse().ok()?;
+                Some((s.min().cmp(&v), s.max().cmp(&v)))
+            }
+            (Statistics::Int64(s), DataType::Int64) => {
+                let v: i64 = value.parse().ok()?;
+                Some((s.min().cmp(&v), s.max().cmp(&v)))
+            }
+            (Statistics::Float(s), DataType::Float32) => {
+                let v: f32 = value.parse().ok()?;
+                Some((s.min().partial_cmp(&v)?, s.max().partial_cmp(&v)?))
+            }
+            (Statistics::Double(s), DataType::Float64) => {
+                let v: f64 = value.parse().ok()?;
+                Some((s.min().partial_cmp(&v)?, s.max().partial_cmp(&v)?))
+            }
+            (Statistics::ByteArray(s), DataType::Utf8) => Some((
+                s.min().data().cmp(value.as_bytes()),
+                s.max().data().cmp(value.as_bytes()),
+            )),
+            _ => None,
+        }
+    }
+
+    // whether a row group with these statistics may contain matching rows, the column
+    // is known to be in the schema by Reader::check
+    fn may_match(&self, row_group: &RowGroupMetaData, schema: &Schema) -> bool {
+        let data_type = match schema.field_with_name(&self.column) {
+            Ok(field) => field.data_type(),
+            Err(_) => return false,
+        };
+        let compared = row_group
+            .columns()
+            .iter()
+            .find(|c| c.column_path().string() == self.column)
+            .and_then(|c| c.statistics())
+            .and_then(|s| self.compare(s, data_type));
+        let (min, max) = match compared {
+            Some(compared) => compared,
+            None => return true,
+        };
+        match self.op {
+            Op::Eq => min != Ordering::Greater && max != Ordering::Less,
+            Op::Ne => !(min == Ordering::Equal && max == Ordering::Equal),
+            Op::Lt => min == Ordering::Less,
+            Op::Le => min != Ordering::Greater,
+            Op::Gt => max == Ordering::Greater,
+            Op::Ge => max != Ordering::Less,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Reader {
+    batch_size: usize,
+    schema: Option<Schema>,
+    inferred_schema_output: Option<String>,
+    columns: Option<Vec<String>>,
+    row_groups: Option<Vec<usize>>,
+    predicates: Vec<Predicate>,
+}
+
+impl Reader {
+    pub fn new(matches: &ArgMatches) -> Self {
+        let columns = matches.value_of("input-parquet-columns").map(|c| {
+            c.split(',')
+                .map(|name| String::from(name.trim()))
+                .collect()
+        });
+        let row_groups = matches.value_of("input-parquet-row-groups").map(|r| {
+            r.split(',')
+                .map(|i| i.trim().parse().expect("input-parquet-row-groups takes row group indices"))
+                .collect()
+        });
+        let predicates = matches
+            .value_of("input-parquet-row-group-filter")
+            .map(|f| f.split(',').map(Predicate::parse).collect())
+            .unwrap_or_default();
+        Self {
+            batch_size: matches.value_of_t("batch-size").unwrap(),
+            schema: None,
+            inferred_schema_output: matches.value_of("inferred-schema-output").map(String::from),
+            columns,
+            row_groups,
+            predicates,
+        }
+    }
+
+    pub fn cmd_args() -> CmdArg {
+        CmdArg::new(vec![
+            CmdArgEntry::new(
+                "inferred-schema-output",
+                "Write the schema of the input to this file (BigQuery schema)",
+                "inferred-schema-output",
+                true,
+                ArgRequired::False(DefaultValue::None),
+            ),
+            CmdArgEntry::new(
+                "batch-size",
+                "number of records in each files",
+                "batch-size",
+                true,
+                ArgRequired::False(DefaultValue::String(String::from("10000"))),
+            ),
+            CmdArgEntry::new(
+                "input-parquet-columns",
+                "Comma separated names of columns to read",
+                "input-parquet-columns",
+                true,
+                ArgRequired::False(DefaultValue::None),
+            ),
+            CmdArgEntry::new(
+                "input-parquet-row-groups",
+                "Comma separated indices of row groups to read",
+                "input-parquet-row-groups",
+                true,
+                ArgRequired::False(DefaultValue::None),
+            ),
+            CmdArgEntry::new(
+                "input-parquet-row-group-filter",
+                "Skip row groups whose statistics can't match, e.g. 'id>=100,country=JP'",
+                "input-parquet-row-group-filter",
+                true,
+                ArgRequired::False(DefaultValue::None),
+            ),
+        ])
+    }
+
+    pub fn schema(&self) -> Option<&Schema> {
+        self.schema.as_ref()
+    }
+
+    pub fn set_schema(&mut self, schema: Schema) {
+        self.schema = Some(schema);
+    }
+
+    pub fn inferred_schema_output(&self) -> Option<&String> {
+        self.inferred_schema_output.as_ref()
+    }
+
+    pub fn finish(&self) -> Result<(), ArrowError> {
+        Ok(())
+    }
+
+    fn projection(&self, schema: &Schema) -> Result<Vec<usize>, ArrowError> {
+        match &self.columns {
+            Some(columns) => columns.iter().map(|name| schema.index_of(name)).collect(),
+            None => Ok((0..schema.fields().len()).collect()),
+        }
+    }
+
+    // fails if a row group index or a filtered column is not in the file
+    fn check(&self, file_reader: &dyn FileReader, schema: &Schema) -> Result<(), ArrowError> {
+        let num_row_groups = file_reader.num_row_groups();
+        if let Some(i) = self.row_groups.iter().flatten().find(|i| **i >= num_row_groups) {
+            return Err(ArrowError::InvalidArgumentError(format!(
+                "input-parquet-row-groups index {} is out of range for {} row groups",
+                i, num_row_groups
+            )));
+        }
+        for predicate in &self.predicates {
+            schema.field_with_name(&predicate.column).map_err(|_| {
+                ArrowError::InvalidArgumentError(format!(
+                    "input-parquet-row-group-filter column {} is not in the file",
+                    predicate.column
+                ))
+            })?;
+        }
+        Ok(())
+    }
+
+    // the schema is taken from the file itself, which is checked against the options here
+    // so that a mistake fails before anything is loaded
+    pub fn infer_schema(
+        &self,
+        input: Input,
+    ) -> Result<(Schema, Input), ArrowError> {
+        let (file_reader, input) = open(input)?;
+        let schema = ParquetFileArrowReader::new(file_reader.clone())
+            .get_schema()
+            .map_err(parquet_error)?;
+        self.check(file_reader.as_ref(), &schema)?;
+        let schema = schema.project(&self.projection(&schema)?)?;
+        Ok((schema, input))
+    }
+
+    fn record_batches(
+        &self,
+        input: Input,
+    ) -> Result<impl Iterator<Item = Result<SourceBatch, ArrowError>> + Send, ArrowError> {
+        let (file_reader, _) = open(input)?;
+        let schema = ParquetFileArrowReader::new(file_reader.clone())
+            .get_schema()
+            .map_err(parquet_error)?;
+        self.check(file_reader.as_ref(), &schema)?;
+        let projection = self.projection(&schema)?;
+        let target_schema = Arc::new(self.schema.clone().expect("schema is not set"));
+
+        // the row groups read, and the 1-based number in the file of each row read
+        let mut selected = vec![];
+        let mut rows = vec![];
+        let mut first_row = 1;
+        for (i, row_group) in file_reader.metadata().row_groups().iter().enumerate() {
+            let num_rows = row_group.num_rows() as usize;
+            if self.row_groups.as_ref().map_or(true, |r| r.contains(&i))
+                && self.predicates.iter().all(|p| p.may_match(row_group, &schema))
+            {
+                selected.push(i);
+                rows.push(first_row..first_row + num_rows);
+            }
+            first_row += num_rows;
+        }
+        let mut rows = rows.into_iter().flatten();
+
+        let file_reader = RowGroups::new(file_reader, selected);
+        let mask = ProjectionMask::roots(file_reader.metadata().file_metadata().schema_descr(), projection);
+        let batches = ParquetFileArrowReader::new(Arc::new(file_reader))
+            .get_record_reader_by_columns(mask, self.batch_size)
+            .map_err(parquet_error)?;
+        Ok(batches.map(move |batch| {
+            let batch = conform(batch?, &target_schema)?;
+            let source_rows = rows.by_ref().take(batch.num_rows()).collect();
+            Ok((batch, source_rows))
+        }))
+    }
+
+    pub fn stream(&self, input: Input, _path: &str) -> BoxStream<'static, Result<SourceBatch, ArrowError>> {
+        match self.record_batches(input) {
+            // async reader needs AsyncRead + AsyncSeek, which stdin is not
+            Ok(batches) => Box::pin(iter(batches)),
+            Err(e) => Box::pin(iter(vec![Err(e)])),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::datatypes::Field;
+    use parquet::data_type::ByteArray;
+    use parquet::file::metadata::ColumnChunkMetaData;
+    use parquet::schema::parser::parse_message_type;
+    use parquet::schema::types::SchemaDescriptor;
+
+    fn schema() -> Schema {
+        Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("country", DataType::Utf8, true),
+        ])
+    }
+
+    // a row group whose id and country range over these values
+    fn row_group(ids: (i64, i64), countries: (&str, &str)) -> RowGroupMetaData {
+        let message = "message schema { REQUIRED INT64 id; OPTIONAL BYTE_ARRAY country (UTF8); }";
+        let descriptor = Arc::new(SchemaDescriptor::new(Arc::new(parse_message_type(message).unwrap())));
+        let statistics = [
+            Statistics::int64(Some(ids.0), Some(ids.1), None, 0, false),
+            Statistics::byte_array(
+                Some(ByteArray::from(countries.0)),
+                Some(ByteArray::from(countries.1)),
+                None,
+                0,
+                false,
+            ),
+        ];
+        let columns = statistics
+            .into_iter()
+            .enumerate()
+            .map(|(i, s)| {
+                ColumnChunkMetaData::builder(descriptor.column(i))
+                    .set_statistics(s)
+                    .build()
+                    .unwrap()
+            })
+            .collect();
+        RowGroupMetaData::builder(descriptor)
+            .set_num_rows(100)
+            .set_column_metadata(columns)
+            .build()
+            .unwrap()
+    }
+
+    fn may_match(predicate: &str, row_group: &RowGroupMetaData) -> bool {
+        Predicate::parse(predicate).may_match(row_group, &schema())
+    }
+
+    #[test]
+    fn parses_predicates() {
+        let predicate = Predicate::parse(" country = JP ");
+        assert_eq!((predicate.column.as_str(), predicate.value.as_str()), ("country", "JP"));
+        assert!(matches!(predicate.op, Op::Eq));
+        assert!(matches!(Predicate::parse("id!=1").op, Op::Ne));
+        assert!(matches!(Predicate::parse("id<=1").op, Op::Le));
+        assert!(matches!(Predicate::parse("id>=1").op, Op::Ge));
+        assert!(matches!(Predicate::parse("id<1").op, Op::Lt));
+        assert!(matches!(Predicate::parse("id>1").op, Op::Gt));
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid row group filter")]
+    fn rejects_predicates_without_operator() {
+        Predicate::parse("id");
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid row group filter")]
+    fn rejects_predicates_without_column() {
+        Predicate::parse(">=1");
+    }
+
+    #[test]
+    fn matches_integer_statistics() {
+        let group = row_group((10, 20), ("DE", "JP"));
+        for (predicate, expected) in [
+            ("id=15", true),
+            ("id=5", false),
+            ("id=25", false),
+            ("id!=15", true),
+            ("id<10", false),
+            ("id<=10", true),
+            ("id>20", false),
+            ("id>=20", true),
+        ] {
+            assert_eq!(may_match(predicate, &group), expected, "{}", predicate);
+        }
+        assert!(!may_match("id!=15", &row_group((15, 15), ("DE", "JP"))));
+    }
+
+    #[test]
+    fn matches_string_statistics() {
+        let group = row_group((10, 20), ("DE", "JP"));
+        assert!(may_match("country=FR", &group));
+        assert!(!may_match("country=US", &group));
+        assert!(!may_match("country<DE", &group));
+    }
+
+    #[test]
+    fn keeps_row_groups_that_cant_be_compared() {
+        let group = row_group((10, 20), ("DE", "JP"));
+        assert!(may_match("id=abc", &group));
+        assert!(may_match("id=1.5", &group));
+    }
+}
@@ -0,0 +1,140 @@
+use crate::cli::{ArgRequired, CmdArg, CmdArgEntry, DefaultValue};
+use arrow::error::ArrowError;
+use clap::ArgMatches;
+use serde_json::json;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::sync::{Arc, Mutex};
+
+// records read before a percentage is checked, so that a bad record early on doesn't fail the run
+const MIN_PERCENT_SAMPLE: usize = 1000;
+
+#[derive(Clone)]
+enum MaxBadRecords {
+    Count(usize),
+    Percent(f64),
+}
+
+// Counts records that can't be read, writes them to the reject file and fails
+// once they exceed --max-bad-records. Clones share the counts and the file.
+#[derive(Clone)]
+pub struct Rejects {
+    max_bad_records: MaxBadRecords,
+    file: Option<Arc<Mutex<BufWriter<File>>>>,
+    // the input being read, written with each rejected record
+    source: Option<String>,
+    // (accepted, rejected)
+    counts: Arc<Mutex<(usize, usize)>>,
+}
+
+impl Rejects {
+    pub fn new(matches: &ArgMatches) -> Self {
+        let max_bad_records = matches.value_of("max-bad-records").unwrap();
+        let max_bad_records = match max_bad_records.strip_suffix('%') {
+            Some(percent) => MaxBadRecords::Percent(percent.trim().parse().unwrap()),
+            None => MaxBadRecords::Count(max_bad_records.parse().unwrap()),
+        };
+        let file = matches
+            .value_of("reject-file")
+            .map(|path| Arc::new(Mutex::new(BufWriter::new(File::create(path).unwrap()))));
+        Self {
+            max_bad_records,
+            file,
+            source: None,
+            counts: Arc::new(Mutex::new((0, 0))),
+        }
+    }
+
+    // a clone for reading one input, sharing the counts and the file
+    pub fn for_source(&self, path: &str) -> Self {
+        Self {
+            source: Some(String::from(path)),
+            ..self.clone()
+        }
+    }
+
+    pub fn cmd_args() -> CmdArg {
+        CmdArg::new(vec![
+            CmdArgEntry::new(
+                "max-bad-records",
+                "Number (e.g. 100) or percentage (e.g. 1%) of bad records tolerated",
+                "max-bad-records",
+                true,
+                ArgRequired::False(DefaultValue::String(String::from("0"))),
+            ),
+            CmdArgEntry::new(
+                "reject-file",
+                "Write bad records with line number and reason to this file (NDJSON)",
+                "reject-file",
+                true,
+                ArgRequired::False(DefaultValue::None),
+            ),
+        ])
+    }
+
+    // the reject file is only written if raw records are worth keeping
+    pub fn keeps_records(&self) -> bool {
+        self.file.is_some()
+    }
+
+    fn flush(&self) -> Result<(), ArrowError> {
+        if let Some(file) = &self.file {
+            file.lock().unwrap().flush()?;
+        }
+        Ok(())
+    }
+
+    pub fn accept(&self, num_records: usize) -> Result<(), ArrowError> {
+        let counts = {
+            let mut counts = self.counts.lock().unwrap();
+            counts.0 += num_records;
+            *counts
+        };
+        self.check_percent(counts, MIN_PERCENT_SAMPLE)
+    }
+
+    // fails once the share of rejected records exceeds a percentage, after min_total records
+    fn check_percent(&self, (accepted, rejected): (usize, usize), min_total: usize) -> Result<(), ArrowError> {
+        if let MaxBadRecords::Percent(max) = self.max_bad_records {
+            let total = accepted + rejected;
+            if total > 0 && total >= min_total && rejected as f64 * 100.0 / total as f64 > max {
+                self.flush()?;
+                return Err(ArrowError::ParseError(format!(
+                    "too many bad records ({} of {})",
+                    rejected, total
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn reject(&self, line: usize, record: &str, reason: &str) -> Result<(), ArrowError> {
+        if let Some(file) = &self.file {
+            let mut file = file.lock().unwrap();
+            let rejected = json!({"file": self.source, "line": line, "reason": reason, "record": record});
+            writeln!(file, "{}", rejected)?;
+        }
+        let counts = {
+            let mut counts = self.counts.lock().unwrap();
+            counts.1 += 1;
+            *counts
+        };
+        match self.max_bad_records {
+            MaxBadRecords::Count(max) if counts.1 > max => {
+                self.flush()?;
+                Err(ArrowError::ParseError(format!(
+                    "too many bad records ({}), line {}: {}",
+                    counts.1, line, reason
+                )))
+            }
+            _ => self.check_percent(counts, MIN_PERCENT_SAMPLE),
+        }
+    }
+
+    // a percentage is checked on all records read, however few
+    pub fn finish(&self) -> Result<(), ArrowError> {
+        self.flush()?;
+        let counts = *self.counts.lock().unwrap();
+        self.check_percent(counts, 0)
+    }
+}
@@ -0,0 +1,67 @@
+mod arrow_json;
+mod avro;
+mod bigquery;
+mod ddl;
+mod json_schema;
+
+use crate::error::GenericError;
+use arrow::datatypes::Schema;
+use std::fs;
+
+pub use avro::{from_value as from_avro_value, to_value as to_avro_value};
+pub use bigquery::save_schema;
+
+pub fn get_schema(
+    schema_file_path: String,
+    schema_format: &str,
+) -> Result<Schema, Box<dyn std::error::Error>> {
+    let content = fs::read_to_string(schema_file_path.as_str())?;
+    match schema_format {
+        "bigquery" => bigquery::parse(&content),
+        "arrow" => arrow_json::parse(&content),
+        "avro" => avro::parse(&content),
+        "json-schema" => json_schema::parse(&content),
+        "ddl" => ddl::parse(&content),
+        unknown => Err(Box::new(GenericError {
+            message: format!("unknown schema format: {}", unknown),
+        })),
+    }
+}
+
+// formats format_schema can write
+pub const OUTPUT_SCHEMA_FORMATS: [&str; 4] = ["bigquery", "arrow", "avro", "ddl"];
+
+pub fn format_schema(
+    schema: &Schema,
+    schema_format: &str,
+    name: &str,
+) -> Result<(String, &'static str), Box<dyn std::error::Error>> {
+    match schema_format {
+        "bigquery" => Ok((bigquery::format(schema)?, "json")),
+        "arrow" => Ok((arrow_json::format(schema)?, "json")),
+        "avro" => Ok((avro::format(schema, name)?, "avsc")),
+        "ddl" => Ok((ddl::format(schema, name)?, "sql")),
+        unknown => Err(Box::new(GenericError {
+            message: format!("unknown schema format: {}", unknown),
+        })),
+    }
+}
+
+// table/record name for a sidecar schema, derived from an output prefix like "dir/records_"
+pub fn schema_name(prefix: &str) -> String {
+    let base = prefix.rsplit('/').next().unwrap_or(prefix);
+    let name: String = base
+        .trim_matches(|c: char| !c.is_ascii_alphanumeric())
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if name.is_empty() {
+        String::from("records")
+    } else {
+        name
+    }
+}
+
+pub fn schema_file_name(prefix: &str, extension: &str) -> String {
+    format!("{}schema.{}", prefix, extension)
+}
@@ -0,0 +1,11 @@
+use arrow::datatypes::Schema;
+use serde_json::Value;
+
+pub fn parse(content: &str) -> Result<Schema, Box<dyn std::error::Error>> {
+    let value: Value = serde_json::from_str(content)?;
+    Ok(Schema::from(&value)?)
+}
+
+pub fn format(schema: &Schema) -> Result<String, Box<dyn std::error::Error>> {
+    Ok(serde_json::to_string_pretty(&schema.to_json())?)
+}
@@ -0,0 +1,320 @@
+use crate::error::GenericError;
+use arrow::datatypes::{DataType, Field, Schema, TimeUnit};
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+
+fn error(message: String) -> Box<dyn std::error::Error> {
+    Box::new(GenericError { message })
+}
+
+// records, enums and fixed types parsed so far, by name and by namespace-qualified name
+type Names = HashMap<String, DataType>;
+
+fn register(names: &mut Names, obj: &Map<String, Value>, t: &DataType) {
+    let name = match obj.get("name").and_then(Value::as_str) {
+        Some(name) => name,
+        None => return,
+    };
+    names.insert(String::from(name), t.clone());
+    if let Some((_, short)) = name.rsplit_once('.') {
+        names.insert(String::from(short), t.clone());
+    } else if let Some(namespace) = obj.get("namespace").and_then(Value::as_str) {
+        names.insert(format!("{}.{}", namespace, name), t.clone());
+    }
+}
+
+fn primitive_type(name: &str, names: &Names) -> Result<DataType, Box<dyn std::error::Error>> {
+    match name {
+        "null" => Ok(DataType::Null),
+        "boolean" => Ok(DataType::Boolean),
+        "int" => Ok(DataType::Int32),
+        "long" => Ok(DataType::Int64),
+        "float" => Ok(DataType::Float32),
+        "double" => Ok(DataType::Float64),
+        "bytes" => Ok(DataType::Binary),
+        "string" => Ok(DataType::Utf8),
+        _ => names
+            .get(name)
+            .or_else(|| names.get(name.rsplit('.').next().unwrap_or(name)))
+            .cloned()
+            .ok_or_else(|| error(format!("unknown Avro type: {}", name))),
+    }
+}
+
+fn logical_type(obj: &Map<String, Value>) -> Option<DataType> {
+    let utc = Some(String::from("UTC"));
+    match obj.get("logicalType").and_then(Value::as_str)? {
+        "date" => Some(DataType::Date32),
+        "time-millis" => Some(DataType::Time32(TimeUnit::Millisecond)),
+        "time-micros" => Some(DataType::Time64(TimeUnit::Microsecond)),
+        "timestamp-millis" => Some(DataType::Timestamp(TimeUnit::Millisecond, utc)),
+        "timestamp-micros" => Some(DataType::Timestamp(TimeUnit::Microsecond, utc)),
+        "local-timestamp-millis" => Some(DataType::Timestamp(TimeUnit::Millisecond, None)),
+        "local-timestamp-micros" => Some(DataType::Timestamp(TimeUnit::Microsecond, None)),
+        "decimal" => {
+            let precision = obj.get("precision").and_then(Value::as_u64)?;
+            let scale = obj.get("scale").and_then(Value::as_u64).unwrap_or(0);
+            Some(DataType::Decimal(precision as usize, scale as usize))
+        }
+        "uuid" => Some(DataType::Utf8),
+        // unknown logical types fall back to the underlying type
+        _ => None,
+    }
+}
+
+// returns the Arrow type and whether the Avro type accepts null
+fn avro_type(
+    value: &Value,
+    names: &mut Names,
+) -> Result<(DataType, bool), Box<dyn std::error::Error>> {
+    match value {
+        Value::String(name) => Ok((primitive_type(name, names)?, name == "null")),
+        Value::Array(branches) => {
+            let non_null: Vec<&Value> = branches
+                .iter()
+                .filter(|b| b.as_str() != Some("null"))
+                .collect();
+            if non_null.len() != 1 {
+                return Err(error(format!("unsupported Avro union: {}", value)));
+            }
+            let (t, _) = avro_type(non_null[0], names)?;
+            Ok((t, non_null.len() < branches.len()))
+        }
+        Value::Object(obj) => {
+            if let Some(t) = logical_type(obj) {
+                register(names, obj, &t);
+                return Ok((t, false));
+            }
+            let type_ = obj
+                .get("type")
+                .ok_or_else(|| error(format!("Avro type without \"type\": {}", value)))?;
+            match type_.as_str() {
+                Some("record") => {
+                    let t = DataType::Struct(record_fields(obj, names)?);
+                    register(names, obj, &t);
+                    Ok((t, false))
+                }
+                Some("array") => {
+                    let items = obj
+                        .get("items")
+                        .ok_or_else(|| error(String::from("Avro array without \"items\"")))?;
+                    let (t, nullable) = avro_type(items, names)?;
+                    Ok((DataType::List(Box::new(Field::new("item", t, nullable))), false))
+                }
+                Some("map") => {
+                    let values = obj
+                        .get("values")
+                        .ok_or_else(|| error(String::from("Avro map without \"values\"")))?;
+                    let (t, nullable) = avro_type(values, names)?;
+                    let entries = DataType::Struct(vec![
+                        Field::new("key", DataType::Utf8, false),
+                        Field::new("value", t, nullable),
+                    ]);
+                    Ok((DataType::Map(Box::new(Field::new("entries", entries, false)), false), false))
+                }
+                Some("enum") => {
+                    register(names, obj, &DataType::Utf8);
+                    Ok((DataType::Utf8, false))
+                }
+                Some("fixed") => {
+                    let size = obj
+                        .get("size")
+                        .and_then(Value::as_i64)
+                        .ok_or_else(|| error(String::from("Avro fixed without \"size\"")))?;
+                    let t = DataType::FixedSizeBinary(size as i32);
+                    register(names, obj, &t);
+                    Ok((t, false))
+                }
+                _ => avro_type(type_, names),
+            }
+        }
+        _ => Err(error(format!("invalid Avro type: {}", value))),
+    }
+}
+
+fn record_fields(
+    record: &Map<String, Value>,
+    names: &mut Names,
+) -> Result<Vec<Field>, Box<dyn std::error::Error>> {
+    let fields = record
+        .get("fields")
+        .and_then(Value::as_array)
+        .ok_or_else(|| error(String::from("Avro record without \"fields\"")))?;
+    fields
+        .iter()
+        .map(|f| {
+            let name = f
+                .get("name")
+                .and_then(Value::as_str)
+                .ok_or_else(|| error(format!("Avro field without \"name\": {}", f)))?;
+            let type_ = f
+                .get("type")
+                .ok_or_else(|| error(format!("Avro field without \"type\": {}", name)))?;
+            let (t, nullable) = avro_type(type_, names)?;
+            Ok(Field::new(name, t, nullable))
+        })
+        .collect()
+}
+
+pub fn from_value(value: &Value) -> Result<Schema, Box<dyn std::error::Error>> {
+    match avro_type(value, &mut Names::new())? {
+        (DataType::Struct(fields), _) => Ok(Schema::new(fields)),
+        _ => Err(error(String::from("Avro schema must be a record"))),
+    }
+}
+
+pub fn parse(content: &str) -> Result<Schema, Box<dyn std::error::Error>> {
+    let value: Value = serde_json::from_str(content)?;
+    from_value(&value)
+}
+
+// path is the dotted name of the field from the top record, which names nested
+// records and fixed types uniquely
+fn to_avro_type(field: &Field, path: &str) -> Result<Value, Box<dyn std::error::Error>> {
+    let t = match field.data_type() {
+        DataType::Null => return Ok(json!("null")),
+        DataType::Boolean => json!("boolean"),
+        DataType::Int8 | DataType::Int16 | DataType::Int32 | DataType::UInt8 | DataType::UInt16 => {
+            json!("int")
+        }
+        DataType::Int64 | DataType::UInt32 => json!("long"),
+        // above i64::MAX it doesn't fit a long
+        DataType::UInt64 => json!({"type": "bytes", "logicalType": "decimal", "precision": 20, "scale": 0}),
+        DataType::Float16 | DataType::Float32 => json!("float"),
+        DataType::Float64 => json!("double"),
+        DataType::Utf8 | DataType::LargeUtf8 => json!("string"),
+        DataType::Binary | DataType::LargeBinary => json!("bytes"),
+        DataType::FixedSizeBinary(size) => json!({"type": "fixed", "name": path, "size": size}),
+        DataType::Date32 | DataType::Date64 => json!({"type": "int", "logicalType": "date"}),
+        DataType::Time32(_) => json!({"type": "int", "logicalType": "time-millis"}),
+        DataType::Time64(_) => json!({"type": "long", "logicalType": "time-micros"}),
+        DataType::Timestamp(unit, tz) => {
+            let precision = match unit {
+                TimeUnit::Second | TimeUnit::Millisecond => "millis",
+                TimeUnit::Microsecond | TimeUnit::Nanosecond => "micros",
+            };
+            let logical_type = match tz {
+                Some(_) => format!("timestamp-{}", precision),
+                None => format!("local-timestamp-{}", precision),
+            };
+            json!({"type": "long", "logicalType": logical_type})
+        }
+        DataType::Decimal(precision, _) if *precision > 38 => {
+            return Err(error(format!("decimal precision above 38: {}", precision)))
+        }
+        DataType::Decimal(precision, scale) => {
+            json!({"type": "bytes", "logicalType": "decimal", "precision": precision, "scale": scale})
+        }
+        DataType::List(item) | DataType::LargeList(item) => {
+            json!({"type": "array", "items": to_avro_type(item, path)?})
+        }
+        DataType::Struct(fields) => to_avro_record(path, fields)?,
+        unsupported => {
+            return Err(error(format!("type not supported in Avro schema: {:?}", unsupported)))
+        }
+    };
+    if field.is_nullable() {
+        Ok(json!(["null", t]))
+    } else {
+        Ok(t)
+    }
+}
+
+fn to_avro_record(name: &str, fields: &[Field]) -> Result<Value, Box<dyn std::error::Error>> {
+    let mut avro_fields = vec![];
+    for field in fields {
+        let path = format!("{}.{}", name, field.name());
+        let mut avro_field = json!({"name": field.name(), "type": to_avro_type(field, &path)?});
+        if field.is_nullable() {
+            avro_field["default"] = Value::Null;
+        }
+        avro_fields.push(avro_field);
+    }
+    Ok(json!({"type": "record", "name": name, "fields": avro_fields}))
+}
+
+pub fn to_value(schema: &Schema, name: &str) -> Result<Value, Box<dyn std::error::Error>> {
+    to_avro_record(name, schema.fields())
+}
+
+pub fn format(schema: &Schema, name: &str) -> Result<String, Box<dyn std::error::Error>> {
+    Ok(serde_json::to_string_pretty(&to_value(schema, name)?)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_records() {
+        let schema = parse(
+            r#"{"type": "record", "name": "r", "namespace": "ns", "fields": [
+                {"name": "id", "type": "long"},
+                {"name": "name", "type": ["null", "string"]},
+                {"name": "amount", "type": {"type": "bytes", "logicalType": "decimal", "precision": 10, "scale": 2}},
+                {"name": "at", "type": {"type": "long", "logicalType": "timestamp-micros"}},
+                {"name": "home", "type": {"type": "record", "name": "Address", "fields": [
+                    {"name": "city", "type": "string"}
+                ]}},
+                {"name": "work", "type": ["null", "ns.Address"]},
+                {"name": "tags", "type": {"type": "array", "items": "string"}}
+            ]}"#,
+        )
+        .unwrap();
+        let address = DataType::Struct(vec![Field::new("city", DataType::Utf8, false)]);
+        let utc = Some(String::from("UTC"));
+        assert_eq!(
+            schema.fields(),
+            &vec![
+                Field::new("id", DataType::Int64, false),
+                Field::new("name", DataType::Utf8, true),
+                Field::new("amount", DataType::Decimal(10, 2), false),
+                Field::new("at", DataType::Timestamp(TimeUnit::Microsecond, utc), false),
+                Field::new("home", address.clone(), false),
+                Field::new("work", address, true),
+                Field::new(
+                    "tags",
+                    DataType::List(Box::new(Field::new("item", DataType::Utf8, false))),
+                    false
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_schemas() {
+        assert!(parse(r#""long""#).is_err());
+        assert!(parse(r#"{"type": "record", "name": "r", "fields": [{"name": "a", "type": "Unknown"}]}"#).is_err());
+        assert!(parse(r#"{"type": "record", "name": "r", "fields": [{"name": "a", "type": ["int", "string"]}]}"#).is_err());
+        assert!(parse(r#"{"type": "record", "name": "r"}"#).is_err());
+    }
+
+    #[test]
+    fn formats_what_it_parses() {
+        let inner = DataType::Struct(vec![Field::new("value", DataType::Int64, true)]);
+        let schema = Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("name", DataType::Utf8, true),
+            Field::new("amount", DataType::Decimal(10, 2), false),
+            Field::new("a", DataType::Struct(vec![Field::new("inner", inner.clone(), false)]), false),
+            Field::new("b", DataType::Struct(vec![Field::new("inner", inner, false)]), false),
+        ]);
+        assert_eq!(parse(&format(&schema, "root").unwrap()).unwrap(), schema);
+    }
+
+    #[test]
+    fn names_nested_records_by_path() {
+        let inner = DataType::Struct(vec![Field::new("value", DataType::Int64, false)]);
+        let schema = Schema::new(vec![
+            Field::new("a", DataType::Struct(vec![Field::new("inner", inner.clone(), false)]), false),
+            Field::new("b", DataType::Struct(vec![Field::new("inner", inner, false)]), false),
+            Field::new("n", DataType::UInt64, false),
+        ]);
+        let value = to_value(&schema, "root").unwrap();
+        assert_eq!(value["fields"][0]["type"]["name"], "root.a");
+        assert_eq!(value["fields"][0]["type"]["fields"][0]["type"]["name"], "root.a.inner");
+        assert_eq!(value["fields"][1]["type"]["fields"][0]["type"]["name"], "root.b.inner");
+        assert_eq!(value["fields"][2]["type"]["logicalType"], "decimal");
+        assert_eq!(value["fields"][2]["type"]["precision"], 20);
+    }
+}
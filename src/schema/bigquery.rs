@@ -0,0 +1,116 @@
+use crate::error::{GenericError, UnknownTypeError};
+use arrow::datatypes::{DataType, Field, Schema, TimeUnit};
+use serde::{Deserialize, Serialize};
+use std::{fs::File, io::BufWriter};
+
+#[derive(Serialize, Deserialize)]
+struct BigQueryColumnDefinition {
+    name: String,
+    r#type: String,
+    mode: String,
+    // sub-columns of a RECORD column
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    fields: Vec<BigQueryColumnDefinition>,
+}
+
+fn create_field(
+    column_definition: &BigQueryColumnDefinition,
+) -> Result<Field, Box<dyn std::error::Error>> {
+    let name = &column_definition.name;
+    let t = match column_definition.r#type.as_str() {
+        "BOOL" => Ok(DataType::Boolean),
+        "STRING" => Ok(DataType::Utf8),
+        "INTEGER" => Ok(DataType::Int64),
+        "FLOAT" => Ok(DataType::Float64),
+        "NUMERIC" => Ok(DataType::Decimal(38, 9)),
+        "TIMESTAMP" => Ok(DataType::Timestamp(TimeUnit::Microsecond, Some(String::from("UTC")))),
+        "DATETIME" => Ok(DataType::Timestamp(TimeUnit::Microsecond, None)),
+        "DATE" => Ok(DataType::Date32),
+        "RECORD" | "STRUCT" => Ok(DataType::Struct(create_fields(&column_definition.fields)?)),
+        unknown => Err(UnknownTypeError {
+            type_name: String::from(unknown),
+        }),
+    }?;
+    match column_definition.mode.as_str() {
+        "REPEATED" => Ok(Field::new(
+            name,
+            DataType::List(Box::new(Field::new("item", t, true))),
+            true,
+        )),
+        mode => Ok(Field::new(name, t, mode == "NULLABLE")),
+    }
+}
+
+fn create_fields(
+    column_definitions: &[BigQueryColumnDefinition],
+) -> Result<Vec<Field>, Box<dyn std::error::Error>> {
+    column_definitions.iter().map(create_field).collect()
+}
+
+pub fn parse(content: &str) -> Result<Schema, Box<dyn std::error::Error>> {
+    let schema: Vec<BigQueryColumnDefinition> = serde_json::from_str(content)?;
+    Ok(Schema::new(create_fields(&schema)?))
+}
+
+fn bigquery_type(data_type: &DataType) -> Result<&'static str, Box<dyn std::error::Error>> {
+    match data_type {
+        DataType::Boolean => Ok("BOOL"),
+        DataType::Utf8 | DataType::LargeUtf8 => Ok("STRING"),
+        DataType::Int8
+        | DataType::Int16
+        | DataType::Int32
+        | DataType::Int64
+        | DataType::UInt8
+        | DataType::UInt16
+        | DataType::UInt32
+        | DataType::UInt64 => Ok("INTEGER"),
+        DataType::Float16 | DataType::Float32 | DataType::Float64 => Ok("FLOAT"),
+        DataType::Decimal(_, _) => Ok("NUMERIC"),
+        DataType::Timestamp(_, None) => Ok("DATETIME"),
+        DataType::Timestamp(_, Some(_)) => Ok("TIMESTAMP"),
+        DataType::Date32 | DataType::Date64 => Ok("DATE"),
+        DataType::Time32(_) | DataType::Time64(_) => Ok("TIME"),
+        DataType::Binary | DataType::LargeBinary | DataType::FixedSizeBinary(_) => Ok("BYTES"),
+        DataType::Struct(_) => Ok("RECORD"),
+        unsupported => Err(Box::new(GenericError {
+            message: format!("type not supported in BigQuery schema: {:?}", unsupported),
+        })),
+    }
+}
+
+fn column_definition(field: &Field) -> Result<BigQueryColumnDefinition, Box<dyn std::error::Error>> {
+    // lists are REPEATED columns of their item type
+    let (data_type, mode) = match field.data_type() {
+        DataType::List(item) | DataType::LargeList(item) => (item.data_type(), "REPEATED"),
+        t if field.is_nullable() => (t, "NULLABLE"),
+        t => (t, "REQUIRED"),
+    };
+    let fields = match data_type {
+        DataType::Struct(fields) => fields_definitions(fields)?,
+        _ => vec![],
+    };
+    Ok(BigQueryColumnDefinition {
+        name: field.name().clone(),
+        r#type: String::from(bigquery_type(data_type)?),
+        mode: String::from(mode),
+        fields,
+    })
+}
+
+fn fields_definitions(fields: &[Field]) -> Result<Vec<BigQueryColumnDefinition>, Box<dyn std::error::Error>> {
+    fields.iter().map(column_definition).collect()
+}
+
+fn column_definitions(schema: &Schema) -> Result<Vec<BigQueryColumnDefinition>, Box<dyn std::error::Error>> {
+    fields_definitions(schema.fields())
+}
+
+pub fn format(schema: &Schema) -> Result<String, Box<dyn std::error::Error>> {
+    Ok(serde_json::to_string_pretty(&column_definitions(schema)?)?)
+}
+
+pub fn save_schema(schema: &Schema, schema_file_path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let file = File::create(schema_file_path)?;
+    serde_json::to_writer_pretty(BufWriter::new(file), &column_definitions(schema)?)?;
+    Ok(())
+}
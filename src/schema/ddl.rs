@@ -0,0 +1,334 @@
+use crate::error::GenericError;
+use arrow::datatypes::{DataType, Field, Schema, TimeUnit};
+
+fn error(message: String) -> Box<dyn std::error::Error> {
+    Box::new(GenericError { message })
+}
+
+const CONSTRAINT_KEYWORDS: [&str; 9] = [
+    " NOT NULL",
+    " NULL",
+    " DEFAULT",
+    " PRIMARY KEY",
+    " UNIQUE",
+    " REFERENCES",
+    " CHECK",
+    " COLLATE",
+    " CONSTRAINT",
+];
+
+// drops "-- ..." comments, but not "--" in quoted strings like DEFAULT '--'
+fn strip_comments(content: &str) -> String {
+    let mut stripped = String::with_capacity(content.len());
+    let mut quote = None;
+    let mut chars = content.chars().peekable();
+    while let Some(c) = chars.next() {
+        match quote {
+            Some(q) => {
+                if c == q {
+                    quote = None;
+                }
+                stripped.push(c);
+            }
+            None if c == '\'' || c == '"' || c == '`' => {
+                quote = Some(c);
+                stripped.push(c);
+            }
+            None if c == '-' && chars.peek() == Some(&'-') => {
+                while chars.peek().map_or(false, |next| *next != '\n') {
+                    chars.next();
+                }
+            }
+            None => stripped.push(c),
+        }
+    }
+    stripped
+}
+
+// splits on commas outside of parentheses and quotes, e.g. between columns but not in NUMERIC(10, 2)
+fn split_top_level(s: &str) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut depth = 0;
+    let mut start = 0;
+    let mut quote = None;
+    for (i, c) in s.char_indices() {
+        if let Some(q) = quote {
+            if c == q {
+                quote = None;
+            }
+            continue;
+        }
+        match c {
+            '\'' | '"' | '`' => quote = Some(c),
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(&s[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&s[start..]);
+    parts
+}
+
+fn ddl_type(type_name: &str) -> Result<DataType, Box<dyn std::error::Error>> {
+    let (base, args) = match type_name.find('(') {
+        Some(i) => {
+            let end = type_name.rfind(')').unwrap_or(type_name.len());
+            let args: Vec<&str> = type_name[i + 1..end].split(',').map(str::trim).collect();
+            (type_name[..i].trim(), args)
+        }
+        None => (type_name.trim(), vec![]),
+    };
+    match base {
+        "BOOL" | "BOOLEAN" => Ok(DataType::Boolean),
+        "SMALLINT" | "INT2" => Ok(DataType::Int16),
+        "INT" | "INTEGER" | "INT4" => Ok(DataType::Int32),
+        "BIGINT" | "INT8" | "INT64" => Ok(DataType::Int64),
+        "REAL" | "FLOAT4" => Ok(DataType::Float32),
+        "DOUBLE" | "DOUBLE PRECISION" | "FLOAT" | "FLOAT8" | "FLOAT64" => Ok(DataType::Float64),
+        "DECIMAL" | "NUMERIC" => {
+            // a bare NUMERIC is BigQuery's NUMERIC(38, 9), NUMERIC(p) has no fraction as in SQL
+            let (precision, scale) = match args.as_slice() {
+                [] => (38, 9),
+                [precision] => (precision.parse()?, 0),
+                [precision, scale] => (precision.parse()?, scale.parse()?),
+                _ => return Err(error(format!("invalid type: {}", type_name))),
+            };
+            Ok(DataType::Decimal(precision, scale))
+        }
+        "CHAR" | "CHARACTER" | "VARCHAR" | "CHARACTER VARYING" | "TEXT" | "STRING" => {
+            Ok(DataType::Utf8)
+        }
+        "DATE" => Ok(DataType::Date32),
+        "TIME" => Ok(DataType::Time64(TimeUnit::Microsecond)),
+        "TIMESTAMP" | "DATETIME" | "TIMESTAMP WITHOUT TIME ZONE" => {
+            Ok(DataType::Timestamp(TimeUnit::Microsecond, None))
+        }
+        "TIMESTAMPTZ" | "TIMESTAMP WITH TIME ZONE" => Ok(DataType::Timestamp(
+            TimeUnit::Microsecond,
+            Some(String::from("UTC")),
+        )),
+        "BYTEA" | "BLOB" | "BINARY" | "VARBINARY" | "BYTES" => Ok(DataType::Binary),
+        unknown => Err(error(format!("unknown DDL type: {}", unknown))),
+    }
+}
+
+fn column_name(definition: &str) -> Option<(String, &str)> {
+    let quote = definition.chars().next()?;
+    if quote == '"' || quote == '`' {
+        let end = definition[1..].find(quote)? + 1;
+        Some((String::from(&definition[1..end]), &definition[end + 1..]))
+    } else {
+        let end = definition.find(char::is_whitespace)?;
+        Some((String::from(&definition[..end]), &definition[end..]))
+    }
+}
+
+// "(" or "name (" after KEY or INDEX, but not a column type like "key VARCHAR(10)"
+fn is_index_columns(rest: &[&str]) -> bool {
+    match rest {
+        ["(", ..] => true,
+        [name, "(", ..] => ddl_type(name).is_err(),
+        _ => false,
+    }
+}
+
+// table constraints like PRIMARY KEY (a), so that columns named key or check are kept
+fn is_table_constraint(definition: &str) -> bool {
+    let words = definition.to_uppercase().replace('(', " ( ");
+    let words: Vec<&str> = words.split_whitespace().collect();
+    match words.as_slice() {
+        ["PRIMARY", "KEY", "(", ..]
+        | ["FOREIGN", "KEY", "(", ..]
+        | ["UNIQUE", "(", ..]
+        | ["CHECK", "(", ..] => true,
+        ["UNIQUE", "KEY" | "INDEX", rest @ ..] | ["KEY" | "INDEX", rest @ ..] => {
+            is_index_columns(rest)
+        }
+        ["CONSTRAINT", _, kind, ..] => {
+            matches!(*kind, "PRIMARY" | "UNIQUE" | "FOREIGN" | "CHECK" | "EXCLUDE")
+        }
+        _ => false,
+    }
+}
+
+fn column_field(definition: &str) -> Result<Option<Field>, Box<dyn std::error::Error>> {
+    if is_table_constraint(definition) {
+        return Ok(None);
+    }
+    let (name, rest) = column_name(definition)
+        .ok_or_else(|| error(format!("invalid column definition: {}", definition)))?;
+    let rest = format!(" {}", rest.trim().to_uppercase());
+    let type_end = CONSTRAINT_KEYWORDS
+        .iter()
+        .filter_map(|k| rest.find(k))
+        .min()
+        .unwrap_or(rest.len());
+    let data_type = ddl_type(&rest[..type_end])?;
+    let nullable = !rest.contains(" NOT NULL") && !rest.contains(" PRIMARY KEY");
+    Ok(Some(Field::new(&name, data_type, nullable)))
+}
+
+// the position of the ')' closing the '(' at open, skipping quoted text
+fn closing_paren(s: &str, open: usize) -> Option<usize> {
+    let mut depth = 0;
+    let mut quote = None;
+    for (i, c) in s[open..].char_indices() {
+        if let Some(q) = quote {
+            if c == q {
+                quote = None;
+            }
+            continue;
+        }
+        match c {
+            '\'' | '"' | '`' => quote = Some(c),
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(open + i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+// clauses after the column list, like PARTITION BY (...) or OPTIONS(...), are ignored
+pub fn parse(content: &str) -> Result<Schema, Box<dyn std::error::Error>> {
+    let content = strip_comments(content);
+    let start = content
+        .find('(')
+        .ok_or_else(|| error(String::from("CREATE TABLE without column list")))?;
+    let end = closing_paren(&content, start)
+        .ok_or_else(|| error(String::from("unbalanced parentheses in CREATE TABLE")))?;
+    let mut fields = vec![];
+    for definition in split_top_level(&content[start + 1..end]) {
+        let definition = definition.trim();
+        if definition.is_empty() {
+            continue;
+        }
+        if let Some(field) = column_field(definition)? {
+            fields.push(field);
+        }
+    }
+    Ok(Schema::new(fields))
+}
+
+fn postgres_type(data_type: &DataType) -> Result<String, Box<dyn std::error::Error>> {
+    match data_type {
+        DataType::Boolean => Ok(String::from("BOOLEAN")),
+        DataType::Int8 | DataType::Int16 | DataType::UInt8 => Ok(String::from("SMALLINT")),
+        DataType::Int32 | DataType::UInt16 => Ok(String::from("INTEGER")),
+        DataType::Int64 | DataType::UInt32 => Ok(String::from("BIGINT")),
+        DataType::UInt64 => Ok(String::from("NUMERIC(20, 0)")),
+        DataType::Float16 | DataType::Float32 => Ok(String::from("REAL")),
+        DataType::Float64 => Ok(String::from("DOUBLE PRECISION")),
+        DataType::Decimal(precision, scale) => Ok(format!("NUMERIC({}, {})", precision, scale)),
+        DataType::Utf8 | DataType::LargeUtf8 => Ok(String::from("TEXT")),
+        DataType::Binary | DataType::LargeBinary | DataType::FixedSizeBinary(_) => {
+            Ok(String::from("BYTEA"))
+        }
+        DataType::Date32 | DataType::Date64 => Ok(String::from("DATE")),
+        DataType::Time32(_) | DataType::Time64(_) => Ok(String::from("TIME")),
+        DataType::Timestamp(_, None) => Ok(String::from("TIMESTAMP")),
+        DataType::Timestamp(_, Some(_)) => Ok(String::from("TIMESTAMPTZ")),
+        DataType::List(item) | DataType::LargeList(item) => {
+            Ok(format!("{}[]", postgres_type(item.data_type())?))
+        }
+        DataType::Struct(_) => Ok(String::from("JSONB")),
+        unsupported => Err(error(format!("type not supported in DDL: {:?}", unsupported))),
+    }
+}
+
+pub fn format(schema: &Schema, name: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let mut columns = vec![];
+    for field in schema.fields() {
+        let not_null = if field.is_nullable() { "" } else { " NOT NULL" };
+        columns.push(format!(
+            "    \"{}\" {}{}",
+            field.name(),
+            postgres_type(field.data_type())?,
+            not_null
+        ));
+    }
+    Ok(format!("CREATE TABLE \"{}\" (\n{}\n);\n", name, columns.join(",\n")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_columns() {
+        let schema = parse(
+            "CREATE TABLE t (
+                id BIGINT NOT NULL,
+                name VARCHAR(10),
+                amount NUMERIC,
+                price NUMERIC(10, 2),
+                qty numeric(5),
+                created TIMESTAMP WITH TIME ZONE,
+                PRIMARY KEY (id)
+            ) PARTITION BY (created);",
+        )
+        .unwrap();
+        let utc = Some(String::from("UTC"));
+        assert_eq!(
+            schema.fields(),
+            &vec![
+                Field::new("id", DataType::Int64, false),
+                Field::new("name", DataType::Utf8, true),
+                Field::new("amount", DataType::Decimal(38, 9), true),
+                Field::new("price", DataType::Decimal(10, 2), true),
+                Field::new("qty", DataType::Decimal(5, 0), true),
+                Field::new("created", DataType::Timestamp(TimeUnit::Microsecond, utc), true),
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_columns_named_like_keywords() {
+        let schema = parse(
+            "CREATE TABLE \"t\" ( -- the key (id)
+                \"key\" INT PRIMARY KEY,
+                note TEXT DEFAULT '--, (',
+                \"check\" BOOLEAN,
+                key VARCHAR(10),
+                CONSTRAINT t_note UNIQUE (note)
+            )",
+        )
+        .unwrap();
+        assert_eq!(
+            schema.fields(),
+            &vec![
+                Field::new("key", DataType::Int32, false),
+                Field::new("note", DataType::Utf8, true),
+                Field::new("check", DataType::Boolean, true),
+                Field::new("key", DataType::Utf8, true),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_definitions() {
+        assert!(parse("CREATE TABLE t").is_err());
+        assert!(parse("CREATE TABLE t (id INT").is_err());
+        assert!(parse("CREATE TABLE t (id FOO)").is_err());
+        assert!(parse("CREATE TABLE t (id NUMERIC(1, 2, 3))").is_err());
+    }
+
+    #[test]
+    fn formats_what_it_parses() {
+        let schema = Schema::new(vec![
+            Field::new("id", DataType::Int64, false),
+            Field::new("amount", DataType::Decimal(10, 2), true),
+            Field::new("at", DataType::Timestamp(TimeUnit::Microsecond, None), true),
+        ]);
+        assert_eq!(parse(&format(&schema, "t").unwrap()).unwrap(), schema);
+    }
+}
@@ -0,0 +1,128 @@
+use crate::error::GenericError;
+use arrow::datatypes::{DataType, Field, Schema, TimeUnit};
+use serde_json::{Map, Value};
+
+fn error(message: String) -> Box<dyn std::error::Error> {
+    Box::new(GenericError { message })
+}
+
+// returns the Arrow type and whether "null" is among the allowed types
+fn property_type(value: &Value) -> Result<(DataType, bool), Box<dyn std::error::Error>> {
+    let obj = value
+        .as_object()
+        .ok_or_else(|| error(format!("invalid JSON Schema: {}", value)))?;
+    let (type_name, nullable) = match obj.get("type") {
+        Some(Value::String(t)) => (t.as_str(), false),
+        Some(Value::Array(types)) => {
+            let non_null: Vec<&str> = types
+                .iter()
+                .filter_map(Value::as_str)
+                .filter(|t| *t != "null")
+                .collect();
+            if non_null.len() != 1 {
+                return Err(error(format!("unsupported JSON Schema type: {}", value)));
+            }
+            (non_null[0], non_null.len() < types.len())
+        }
+        _ => return Err(error(format!("JSON Schema without \"type\": {}", value))),
+    };
+    let t = match type_name {
+        "string" => match obj.get("format").and_then(Value::as_str) {
+            Some("date-time") => DataType::Timestamp(TimeUnit::Microsecond, Some(String::from("UTC"))),
+            Some("date") => DataType::Date32,
+            Some("time") => DataType::Time64(TimeUnit::Microsecond),
+            _ => DataType::Utf8,
+        },
+        "integer" => DataType::Int64,
+        "number" => DataType::Float64,
+        "boolean" => DataType::Boolean,
+        "object" => DataType::Struct(object_fields(obj)?),
+        "array" => {
+            let items = obj
+                .get("items")
+                .ok_or_else(|| error(String::from("JSON Schema array without \"items\"")))?;
+            let (t, nullable) = property_type(items)?;
+            DataType::List(Box::new(Field::new("item", t, nullable)))
+        }
+        unknown => return Err(error(format!("unknown JSON Schema type: {}", unknown))),
+    };
+    Ok((t, nullable))
+}
+
+fn object_fields(obj: &Map<String, Value>) -> Result<Vec<Field>, Box<dyn std::error::Error>> {
+    let required: Vec<&str> = obj
+        .get("required")
+        .and_then(Value::as_array)
+        .map(|r| r.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default();
+    let properties = match obj.get("properties").and_then(Value::as_object) {
+        Some(properties) => properties,
+        None => return Ok(vec![]),
+    };
+    properties
+        .iter()
+        .map(|(name, property)| {
+            let (t, nullable) = property_type(property)?;
+            let nullable = nullable || !required.contains(&name.as_str());
+            Ok(Field::new(name, t, nullable))
+        })
+        .collect()
+}
+
+pub fn parse(content: &str) -> Result<Schema, Box<dyn std::error::Error>> {
+    let value: Value = serde_json::from_str(content)?;
+    match property_type(&value)? {
+        (DataType::Struct(fields), _) => Ok(Schema::new(fields)),
+        _ => Err(error(String::from("JSON Schema must describe an object"))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_objects() {
+        let schema = parse(
+            r#"{"type": "object", "required": ["id", "tags"], "properties": {
+                "id": {"type": "integer"},
+                "name": {"type": ["string", "null"]},
+                "at": {"type": "string", "format": "date-time"},
+                "day": {"type": "string", "format": "date"},
+                "address": {"type": "object", "required": ["city"], "properties": {
+                    "city": {"type": "string"}
+                }},
+                "tags": {"type": "array", "items": {"type": "string"}}
+            }}"#,
+        )
+        .unwrap();
+        let utc = Some(String::from("UTC"));
+        assert_eq!(
+            schema.fields(),
+            &vec![
+                Field::new("id", DataType::Int64, false),
+                Field::new("name", DataType::Utf8, true),
+                Field::new("at", DataType::Timestamp(TimeUnit::Microsecond, utc), true),
+                Field::new("day", DataType::Date32, true),
+                Field::new(
+                    "address",
+                    DataType::Struct(vec![Field::new("city", DataType::Utf8, false)]),
+                    true
+                ),
+                Field::new(
+                    "tags",
+                    DataType::List(Box::new(Field::new("item", DataType::Utf8, false))),
+                    false
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_schemas() {
+        assert!(parse(r#"{"type": "string"}"#).is_err());
+        assert!(parse(r#"{"properties": {}}"#).is_err());
+        assert!(parse(r#"{"type": "object", "properties": {"a": {"type": ["string", "integer"]}}}"#).is_err());
+        assert!(parse(r#"{"type": "object", "properties": {"a": {"type": "array"}}}"#).is_err());
+    }
+}
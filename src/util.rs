@@ -1,63 +1,220 @@
+use arrow::array::{Array, StringArray};
+use arrow::compute::kernels::cast_utils::string_to_timestamp_nanos;
 use arrow::record_batch::RecordBatch;
 use arrow::datatypes::SchemaRef;
-use crate::error::UnknownTypeError;
 use arrow::datatypes::{DataType, Field, Schema, TimeUnit};
+use chrono::NaiveDate;
 use futures::channel::mpsc;
 use futures::StreamExt;
-use serde::{Deserialize, Serialize};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use std::{
-    fs::File,
-    io::{BufReader, Cursor, Seek, SeekFrom, Write},
+    io::{BufRead, BufReader, Cursor, Read, Seek, SeekFrom, Write},
 };
+use tokio::sync::watch;
+use tokio::time::{timeout_at, Instant};
 
-#[derive(Serialize, Deserialize)]
-struct BigQueryColumnDefinition {
-    name: String,
-    r#type: String,
-    mode: String,
-}
-
-fn create_field(
-    name: &str,
-    type_: &str,
-    mode: Option<&str>,
-) -> Result<Field, Box<dyn std::error::Error>> {
-    let t = match type_ {
-        "BOOL" => Ok(DataType::Boolean),
-        "STRING" => Ok(DataType::Utf8),
-        "INTEGER" => Ok(DataType::Int64),
-        "FLOAT" => Ok(DataType::Float64),
-        "NUMERIC" => Ok(DataType::Decimal(38, 9)),
-        "TIMESTAMP" => Ok(DataType::Timestamp(TimeUnit::Microsecond, None)),
-        "DATE" => Ok(DataType::Date64),
-        unknown => Err(UnknownTypeError {
-            type_name: String::from(unknown),
-        }),
-    }?;
-    let nullable = if let Some(nullable_) = mode {
-        nullable_ == "NULLABLE"
+// Reads up to `num_lines` lines for schema inference and returns them along with
+// a reader that yields the whole input again, so stdin can be sampled too.
+pub fn sample_lines(
+    file: Box<dyn Read + Send>,
+    num_lines: usize,
+) -> std::io::Result<(Vec<u8>, Box<dyn Read + Send>)> {
+    let mut reader = BufReader::new(file);
+    let mut sample = Vec::new();
+    for _ in 0..num_lines {
+        if reader.read_until(b'\n', &mut sample)? == 0 {
+            break;
+        }
+    }
+    let rest = Cursor::new(sample.clone()).chain(reader);
+    Ok((sample, Box::new(rest)))
+}
+
+// Keeps the bytes read while parsing a header, so the input can be read again from the start.
+pub struct Tee {
+    inner: Box<dyn Read + Send>,
+    read: Vec<u8>,
+}
+
+impl Tee {
+    pub fn new(inner: Box<dyn Read + Send>) -> Self {
+        Self { inner, read: vec![] }
+    }
+
+    pub fn replay(self) -> Box<dyn Read + Send> {
+        Box::new(Cursor::new(self.read).chain(self.inner))
+    }
+}
+
+impl Read for Tee {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.read.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+}
+
+// a CSV delimiter, quote or escape character, e.g. "," or "\\t"
+pub fn single_byte(name: &str, value: &str) -> u8 {
+    match value {
+        "\\t" | "tab" => b'\t',
+        v if v.len() == 1 => v.as_bytes()[0],
+        _ => panic!("{} must be one character", name),
+    }
+}
+
+pub fn is_temporal_candidate(data_type: &DataType) -> bool {
+    matches!(data_type, DataType::Utf8 | DataType::Date32 | DataType::Date64)
+}
+
+// Same fields with every type replaced by Utf8, used to re-read a sample as text.
+pub fn text_schema(schema: &Schema) -> Schema {
+    Schema::new(
+        schema
+            .fields()
+            .iter()
+            .map(|f| Field::new(f.name(), DataType::Utf8, true))
+            .collect(),
+    )
+}
+
+fn infer_temporal_type(values: &StringArray) -> Option<DataType> {
+    let mut all_date = true;
+    let mut all_timestamp = true;
+    let mut seen = false;
+    for value in values.iter().flatten().map(str::trim).filter(|v| !v.is_empty()) {
+        seen = true;
+        all_date = all_date && NaiveDate::parse_from_str(value, "%Y-%m-%d").is_ok();
+        all_timestamp = all_timestamp && string_to_timestamp_nanos(value).is_ok();
+        if !all_date && !all_timestamp {
+            return None;
+        }
+    }
+    if !seen {
+        None
+    } else if all_date {
+        Some(DataType::Date32)
     } else {
-        false
-    };
+        Some(DataType::Timestamp(TimeUnit::Microsecond, Some(String::from("UTC"))))
+    }
+}
+
+// infers the type of a text column, e.g. a CSV column
+pub fn infer_text_type(values: &StringArray) -> DataType {
+    let non_empty: Vec<&str> = values
+        .iter()
+        .flatten()
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .collect();
+    if non_empty.is_empty() {
+        DataType::Null
+    } else if non_empty
+        .iter()
+        .all(|v| v.eq_ignore_ascii_case("true") || v.eq_ignore_ascii_case("false"))
+    {
+        DataType::Boolean
+    } else if non_empty.iter().all(|v| v.parse::<i64>().is_ok()) {
+        DataType::Int64
+    } else if non_empty.iter().all(|v| v.parse::<f64>().is_ok()) {
+        DataType::Float64
+    } else {
+        infer_temporal_type(values).unwrap_or(DataType::Utf8)
+    }
+}
+
+// Arrow only infers a narrow set of date/time notations, so string-like columns are
+// checked again against the sampled text values.
+pub fn refine_schema(schema: &Schema, text_batch: &RecordBatch) -> Schema {
+    let fields = schema
+        .fields()
+        .iter()
+        .map(|field| {
+            if !is_temporal_candidate(field.data_type()) {
+                return field.clone();
+            }
+            let column = match text_batch.schema().index_of(field.name()) {
+                Ok(i) => text_batch.column(i).clone(),
+                Err(_) => return field.clone(),
+            };
+            let values = column.as_any().downcast_ref::<StringArray>().unwrap();
+            match infer_temporal_type(values) {
+                Some(t) => Field::new(field.name(), t, field.is_nullable()),
+                None if field.data_type() == &DataType::Date64 => {
+                    Field::new(field.name(), DataType::Utf8, field.is_nullable())
+                }
+                None => field.clone(),
+            }
+        })
+        .collect();
+    Schema::new(fields)
+}
+
+fn coerce_types(left: &DataType, right: &DataType) -> DataType {
+    match (left, right) {
+        (l, r) if l == r => l.clone(),
+        (DataType::Null, t) | (t, DataType::Null) => t.clone(),
+        (DataType::Int64, DataType::Float64) | (DataType::Float64, DataType::Int64) => {
+            DataType::Float64
+        }
+        (DataType::Date32, DataType::Timestamp(unit, tz))
+        | (DataType::Timestamp(unit, tz), DataType::Date32) => {
+            DataType::Timestamp(unit.clone(), tz.clone())
+        }
+        _ => DataType::Utf8,
+    }
+}
 
-    Ok(Field::new(name, t, nullable))
+// Merges schemas inferred from several inputs, widening conflicting column types.
+pub fn merge_schemas(schemas: Vec<Schema>) -> Schema {
+    let mut fields: Vec<Field> = vec![];
+    for schema in &schemas {
+        for field in schema.fields() {
+            if let Some(merged) = fields.iter_mut().find(|f| f.name() == field.name()) {
+                let data_type = coerce_types(merged.data_type(), field.data_type());
+                *merged = Field::new(field.name(), data_type, true);
+            } else {
+                fields.push(Field::new(field.name(), field.data_type().clone(), true));
+            }
+        }
+    }
+    let fields = fields
+        .into_iter()
+        .map(|f| match f.data_type() {
+            DataType::Null => Field::new(f.name(), DataType::Utf8, true),
+            _ => f,
+        })
+        .collect();
+    Schema::new(fields)
 }
 
-pub fn get_schema(schema_file_path: String) -> Result<Schema, Box<dyn std::error::Error>> {
-    let mut column_definitions = vec![];
-    let file = File::open(schema_file_path.as_str())?;
-    let reader = BufReader::new(file);
-    let schema: Vec<BigQueryColumnDefinition> = serde_json::from_reader(reader)?;
-    for column_definition in &schema {
-        let field = create_field(
-            &column_definition.name,
-            &column_definition.r#type,
-            Some(&column_definition.mode),
-        )?;
-        column_definitions.push(field);
+// parses sizes like "268435456", "256MB" or "1G" into bytes
+pub fn parse_size(size: &str) -> Option<usize> {
+    let size = size.trim().to_uppercase();
+    let digits = size.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let multiplier = match size[digits.len()..].trim_end_matches('B') {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        _ => return None,
+    };
+    digits.trim().parse::<usize>().ok().map(|n| n * multiplier)
+}
+
+// parses durations like "30", "30s", "500ms", "5m" or "1h"
+pub fn parse_duration(duration: &str) -> Option<Duration> {
+    let duration = duration.trim().to_lowercase();
+    let digits = duration.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let value = digits.trim().parse::<u64>().ok()?;
+    match &duration[digits.len()..] {
+        "ms" => Some(Duration::from_millis(value)),
+        "" | "s" => Some(Duration::from_secs(value)),
+        "m" => Some(Duration::from_secs(value * 60)),
+        "h" => Some(Duration::from_secs(value * 3600)),
+        _ => None,
     }
-    Ok(Schema::new(column_definitions))
 }
 
 #[derive(Debug, Default, Clone)]
@@ -73,6 +230,11 @@ impl WriteableCursor {
             .map(|cursor| cursor.into_inner())
     }
 
+    // number of bytes written so far
+    pub fn bytes_written(&self) -> usize {
+        self.buffer.lock().unwrap().get_ref().len()
+    }
+
     pub fn try_clone(&self) -> std::io::Result<Self> {
         Ok(Self {
             buffer: self.buffer.clone(),
@@ -104,6 +266,12 @@ pub struct BatchReceiver {
     current_offset: usize,
     rx: mpsc::UnboundedReceiver<RecordBatch>,
     schema: Option<SchemaRef>,
+    finished: bool,
+    flush_interval: Option<Duration>,
+    // when the oldest row not yet flushed by the loader arrived
+    pending_since: Option<Instant>,
+    rows_received: usize,
+    ack: Option<watch::Sender<usize>>,
 }
 
 impl BatchReceiver {
@@ -113,8 +281,63 @@ impl BatchReceiver {
             current_offset: 0,
             schema: None,
             rx,
+            finished: false,
+            flush_interval: None,
+            pending_since: None,
+            rows_received: 0,
+            ack: None,
         }
     }
+
+    // publishes the number of rows loaded so far each time the loader has flushed
+    pub fn with_ack(mut self, ack: watch::Sender<usize>) -> Self {
+        self.ack = Some(ack);
+        self
+    }
+
+    // makes receive return early once rows have waited this long, see flush_due
+    pub fn with_flush_interval(mut self, flush_interval: Option<Duration>) -> Self {
+        self.flush_interval = flush_interval;
+        self
+    }
+
+    // true once the sender is gone and every row has been received
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    // true if rows have waited longer than the flush interval
+    pub fn flush_due(&self) -> bool {
+        match (self.flush_interval, self.pending_since) {
+            (Some(interval), Some(since)) => since.elapsed() >= interval,
+            _ => false,
+        }
+    }
+
+    // called by the loader once it has written out everything received so far
+    pub fn flushed(&mut self) {
+        let has_rest = self
+            .current_batch
+            .as_ref()
+            .map_or(false, |batch| self.current_offset < batch.num_rows());
+        self.pending_since = if has_rest { Some(Instant::now()) } else { None };
+        if let Some(ack) = &self.ack {
+            let _ = ack.send(self.rows_received);
+        }
+    }
+
+    // None if the flush interval elapses first
+    async fn next_batch(&mut self) -> Option<Option<RecordBatch>> {
+        let next = match (self.flush_interval, self.pending_since) {
+            (Some(interval), Some(since)) => timeout_at(since + interval, self.rx.next()).await.ok()?,
+            _ => self.rx.next().await,
+        };
+        if next.as_ref().map_or(false, |batch| batch.num_rows() > 0) && self.pending_since.is_none() {
+            self.pending_since = Some(Instant::now());
+        }
+        Some(next)
+    }
+
     fn current_batch(&self) -> &Option<RecordBatch> {
         &self.current_batch
     }
@@ -148,7 +371,10 @@ impl BatchReceiver {
         let mut batches = Vec::new();
         while rows_need > 0 {
             if let Some(batch) = self.current_batch() {
-                if batch.num_rows() == 0 { break; }
+                if batch.num_rows() == 0 {
+                    self.finished = true;
+                    break;
+                }
                 let residue = batch.num_rows() - self.current_offset();
                 if residue > rows_need {
                     let sliced = batch.slice(self.current_offset(), rows_need);
@@ -160,19 +386,29 @@ impl BatchReceiver {
                     batches.push(sliced);
                     rows_need -= residue;
                     *self.current_offset_mut() = 0;
-                    *self.current_batch_mut() = self.rx().next().await;
+                    *self.current_batch_mut() = None;
                 }
-            } else if let Some(batch) = self.rx().next().await {
-                *self.schema_mut() = Some(batch.schema());
-                *self.current_batch_mut() = Some(batch);
-                *self.current_offset_mut() = 0;
             } else {
-                break;
+                match self.next_batch().await {
+                    Some(Some(batch)) => {
+                        *self.schema_mut() = Some(batch.schema());
+                        *self.current_batch_mut() = Some(batch);
+                        *self.current_offset_mut() = 0;
+                    }
+                    Some(None) => {
+                        self.finished = true;
+                        break;
+                    }
+                    // flush interval elapsed
+                    None => break,
+                }
             }
         }
 
         if batches.len() > 0 {
-            Some(RecordBatch::concat(self.schema().as_ref().unwrap(), &batches).unwrap())
+            let batch = RecordBatch::concat(self.schema().as_ref().unwrap(), &batches).unwrap();
+            self.rows_received += batch.num_rows();
+            Some(batch)
         } else {
             None
         }
@@ -190,4 +426,52 @@ impl BatchReceiver {
             None
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sizes() {
+        assert_eq!(parse_size("268435456"), Some(268435456));
+        assert_eq!(parse_size("256MB"), Some(256 << 20));
+        assert_eq!(parse_size("64kb"), Some(64 << 10));
+        assert_eq!(parse_size(" 10 M "), Some(10 << 20));
+        assert_eq!(parse_size("1G"), Some(1 << 30));
+        assert_eq!(parse_size("12B"), Some(12));
+        for size in ["", "MB", "1T", "1.5G", "-1"] {
+            assert_eq!(parse_size(size), None, "{}", size);
+        }
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("30"), Some(Duration::from_secs(30)));
+        assert_eq!(parse_duration("30s"), Some(Duration::from_secs(30)));
+        assert_eq!(parse_duration("500ms"), Some(Duration::from_millis(500)));
+        assert_eq!(parse_duration("5m"), Some(Duration::from_secs(300)));
+        assert_eq!(parse_duration(" 1H "), Some(Duration::from_secs(3600)));
+        for duration in ["", "ms", "1d", "-1s", "1.5s"] {
+            assert_eq!(parse_duration(duration), None, "{}", duration);
+        }
+    }
+
+    fn text_type(values: Vec<Option<&str>>) -> DataType {
+        infer_text_type(&StringArray::from(values))
+    }
+
+    #[test]
+    fn infers_text_types() {
+        assert_eq!(text_type(vec![None, Some(""), Some(" ")]), DataType::Null);
+        assert_eq!(text_type(vec![Some("true"), Some("FALSE"), None]), DataType::Boolean);
+        assert_eq!(text_type(vec![Some("1"), Some(" -2 "), Some("")]), DataType::Int64);
+        assert_eq!(text_type(vec![Some("1"), Some("2.5")]), DataType::Float64);
+        assert_eq!(text_type(vec![Some("2024-01-01"), Some("2024-02-29")]), DataType::Date32);
+        assert_eq!(
+            text_type(vec![Some("2024-01-01T00:00:00Z"), Some("2024-01-01 12:34:56")]),
+            DataType::Timestamp(TimeUnit::Microsecond, Some(String::from("UTC")))
+        );
+        assert_eq!(text_type(vec![Some("1"), Some("true")]), DataType::Utf8);
+        assert_eq!(text_type(vec![Some("2024-01-01"), Some("a")]), DataType::Utf8);
+    }
+}
@@ -1,3 +1,5 @@
+#[cfg(any(feature = "writer-csv", feature = "writer-json"))]
+mod compression;
 #[cfg(feature = "writer-parquet")]
 mod parquet;
 #[cfg(feature = "writer-parquet")]
@@ -10,6 +12,26 @@ pub use crate::writer::csv::Writer;
 mod json;
 #[cfg(feature = "writer-json")]
 pub use crate::writer::json::Writer;
+#[cfg(feature = "writer-avro")]
+mod avro;
+#[cfg(feature = "writer-avro")]
+pub use crate::writer::avro::Writer;
+#[cfg(feature = "writer-arrow")]
+mod ipc;
+#[cfg(feature = "writer-arrow")]
+pub use crate::writer::ipc::Writer;
+
+use arrow::record_batch::RecordBatch;
+
+// An output file written batch by batch, so that loaders can roll files by size.
+pub trait FileWriter: Send {
+    fn write(&mut self, batch: &RecordBatch);
+    fn close(self: Box<Self>);
+    // bytes held by the writer that aren't in the output yet, counted towards --target-file-size
+    fn buffered_size(&self) -> usize {
+        0
+    }
+}
 
 #[cfg(not(feature = "writer"))]
 compile_error!("feature writer-* not enabled.");
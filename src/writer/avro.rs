@@ -0,0 +1,216 @@
+use crate::cli::{ArgRequired::False, CmdArg, CmdArgEntry, DefaultValue};
+use crate::error::GenericError;
+use crate::schema::to_avro_value;
+use crate::util::WriteableCursor;
+use crate::writer::FileWriter;
+use apache_avro::types::Value as AvroValue;
+use apache_avro::{Codec, Decimal};
+use arrow::array::{
+    Array, ArrayRef, BinaryArray, BooleanArray, Date32Array, Date64Array, DecimalArray,
+    FixedSizeBinaryArray, Float16Array, Float32Array, Float64Array, Int16Array, Int32Array,
+    Int64Array, Int8Array, LargeBinaryArray, LargeListArray, LargeStringArray, ListArray,
+    StringArray, StructArray, Time32MillisecondArray, Time32SecondArray,
+    Time64MicrosecondArray, Time64NanosecondArray, TimestampMicrosecondArray,
+    TimestampMillisecondArray, TimestampNanosecondArray, TimestampSecondArray, UInt16Array,
+    UInt32Array, UInt64Array, UInt8Array,
+};
+use arrow::datatypes::{DataType, Field, SchemaRef, TimeUnit};
+use arrow::record_batch::RecordBatch;
+use clap::ArgMatches;
+use std::sync::{Arc, Mutex};
+
+macro_rules! value_at {
+    ($array:expr, $t:ty, $row:expr) => {
+        $array.as_any().downcast_ref::<$t>().unwrap().value($row)
+    };
+}
+
+// the Avro value of a cell, typed as in schema::to_avro_value
+fn avro_value(field: &Field, array: &ArrayRef, row: usize) -> AvroValue {
+    // a null column is plain "null" in the schema, not a union
+    if field.is_nullable() && field.data_type() != &DataType::Null {
+        return if array.is_null(row) {
+            AvroValue::Union(0, Box::new(AvroValue::Null))
+        } else {
+            AvroValue::Union(1, Box::new(non_null_value(field, array, row)))
+        };
+    }
+    non_null_value(field, array, row)
+}
+
+fn non_null_value(field: &Field, array: &ArrayRef, row: usize) -> AvroValue {
+    match field.data_type() {
+        DataType::Null => AvroValue::Null,
+        DataType::Boolean => AvroValue::Boolean(value_at!(array, BooleanArray, row)),
+        DataType::Int8 => AvroValue::Int(value_at!(array, Int8Array, row) as i32),
+        DataType::Int16 => AvroValue::Int(value_at!(array, Int16Array, row) as i32),
+        DataType::Int32 => AvroValue::Int(value_at!(array, Int32Array, row)),
+        DataType::UInt8 => AvroValue::Int(value_at!(array, UInt8Array, row) as i32),
+        DataType::UInt16 => AvroValue::Int(value_at!(array, UInt16Array, row) as i32),
+        DataType::Int64 => AvroValue::Long(value_at!(array, Int64Array, row)),
+        DataType::UInt32 => AvroValue::Long(value_at!(array, UInt32Array, row) as i64),
+        DataType::UInt64 => {
+            let value = value_at!(array, UInt64Array, row) as i128;
+            AvroValue::Decimal(Decimal::from(value.to_be_bytes().to_vec()))
+        }
+        DataType::Float16 => AvroValue::Float(value_at!(array, Float16Array, row).to_f32()),
+        DataType::Float32 => AvroValue::Float(value_at!(array, Float32Array, row)),
+        DataType::Float64 => AvroValue::Double(value_at!(array, Float64Array, row)),
+        DataType::Utf8 => AvroValue::String(String::from(value_at!(array, StringArray, row))),
+        DataType::LargeUtf8 => {
+            AvroValue::String(String::from(value_at!(array, LargeStringArray, row)))
+        }
+        DataType::Binary => AvroValue::Bytes(value_at!(array, BinaryArray, row).to_vec()),
+        DataType::LargeBinary => AvroValue::Bytes(value_at!(array, LargeBinaryArray, row).to_vec()),
+        DataType::FixedSizeBinary(size) => AvroValue::Fixed(
+            *size as usize,
+            value_at!(array, FixedSizeBinaryArray, row).to_vec(),
+        ),
+        DataType::Date32 => AvroValue::Date(value_at!(array, Date32Array, row)),
+        DataType::Date64 => {
+            AvroValue::Date((value_at!(array, Date64Array, row) / 86_400_000) as i32)
+        }
+        DataType::Time32(TimeUnit::Second) => {
+            AvroValue::TimeMillis(value_at!(array, Time32SecondArray, row) * 1000)
+        }
+        DataType::Time32(_) => AvroValue::TimeMillis(value_at!(array, Time32MillisecondArray, row)),
+        DataType::Time64(TimeUnit::Nanosecond) => {
+            AvroValue::TimeMicros(value_at!(array, Time64NanosecondArray, row) / 1000)
+        }
+        DataType::Time64(_) => AvroValue::TimeMicros(value_at!(array, Time64MicrosecondArray, row)),
+        DataType::Timestamp(unit, tz) => {
+            let (millis, micros) = match unit {
+                TimeUnit::Second => (Some(value_at!(array, TimestampSecondArray, row) * 1000), None),
+                TimeUnit::Millisecond => (Some(value_at!(array, TimestampMillisecondArray, row)), None),
+                TimeUnit::Microsecond => (None, Some(value_at!(array, TimestampMicrosecondArray, row))),
+                TimeUnit::Nanosecond => {
+                    (None, Some(value_at!(array, TimestampNanosecondArray, row) / 1000))
+                }
+            };
+            // local-timestamp-* isn't a logical type of the Avro library, so they're plain longs
+            match (millis, micros, tz) {
+                (Some(t), _, Some(_)) => AvroValue::TimestampMillis(t),
+                (_, Some(t), Some(_)) => AvroValue::TimestampMicros(t),
+                (Some(t), _, None) | (_, Some(t), None) => AvroValue::Long(t),
+                (None, None, _) => unreachable!(),
+            }
+        }
+        DataType::Decimal(_, _) => {
+            let unscaled = value_at!(array, DecimalArray, row);
+            AvroValue::Decimal(Decimal::from(unscaled.to_be_bytes().to_vec()))
+        }
+        DataType::List(item) => {
+            let values = value_at!(array, ListArray, row);
+            AvroValue::Array((0..values.len()).map(|i| avro_value(item, &values, i)).collect())
+        }
+        DataType::LargeList(item) => {
+            let values = value_at!(array, LargeListArray, row);
+            AvroValue::Array((0..values.len()).map(|i| avro_value(item, &values, i)).collect())
+        }
+        DataType::Struct(fields) => {
+            let array = array.as_any().downcast_ref::<StructArray>().unwrap();
+            AvroValue::Record(
+                fields
+                    .iter()
+                    .zip(array.columns())
+                    .map(|(f, column)| (f.name().clone(), avro_value(f, column, row)))
+                    .collect(),
+            )
+        }
+        // rejected by to_avro_value when the file is opened
+        unsupported => panic!("type not supported in Avro: {:?}", unsupported),
+    }
+}
+
+struct AvroFileWriter {
+    writer: apache_avro::Writer<'static, WriteableCursor>,
+}
+
+impl FileWriter for AvroFileWriter {
+    fn write(&mut self, batch: &RecordBatch) {
+        let schema = batch.schema();
+        for row in 0..batch.num_rows() {
+            let record = schema
+                .fields()
+                .iter()
+                .zip(batch.columns())
+                .map(|(f, column)| (f.name().clone(), avro_value(f, column, row)))
+                .collect();
+            self.writer.append(AvroValue::Record(record)).expect("Writing record");
+        }
+    }
+
+    fn close(mut self: Box<Self>) {
+        self.writer.flush().unwrap();
+    }
+}
+
+#[derive(Clone)]
+pub struct Writer {
+    codec: Codec,
+    file_extension: String,
+    // The last schema parsed, shared by the files written with it. The Avro writer
+    // borrows its schema, so each schema is leaked once to outlive every file.
+    schema: Arc<Mutex<Option<(SchemaRef, &'static apache_avro::Schema)>>>,
+}
+
+impl Writer {
+    pub fn new(matches: &ArgMatches) -> Self {
+        let codec = match matches.value_of("compression").unwrap() {
+            "null" | "none" => Codec::Null,
+            "deflate" => Codec::Deflate,
+            "snappy" => Codec::Snappy,
+            "zstd" => Codec::Zstandard,
+            unknown => panic!("unknown Avro codec: {}", unknown),
+        };
+        Self {
+            codec,
+            file_extension: String::from("avro"),
+            schema: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    pub fn cmd_args() -> CmdArg {
+        CmdArg::new(vec![CmdArgEntry::new(
+            "compression",
+            "Avro codec (null, deflate, snappy or zstd)",
+            "compression",
+            true,
+            False(DefaultValue::String(String::from("deflate"))),
+        )])
+    }
+
+    pub fn file_extension(&self) -> &String {
+        &self.file_extension
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        "application/avro"
+    }
+
+    pub fn content_encoding(&self) -> Option<&'static str> {
+        None
+    }
+
+    // fails on Arrow types that have no Avro counterpart
+    fn avro_schema(&self, schema: SchemaRef) -> Result<&'static apache_avro::Schema, GenericError> {
+        let mut cached = self.schema.lock().unwrap();
+        if let Some((_, avro_schema)) = cached.as_ref().filter(|(s, _)| *s == schema) {
+            return Ok(*avro_schema);
+        }
+        let schema_error = |message: String| GenericError {
+            message: format!("Avro schema error: {}", message),
+        };
+        let avro_schema = to_avro_value(&schema, "record").map_err(|e| schema_error(e.to_string()))?;
+        let avro_schema = apache_avro::Schema::parse(&avro_schema).map_err(|e| schema_error(e.to_string()))?;
+        let avro_schema: &'static apache_avro::Schema = Box::leak(Box::new(avro_schema));
+        *cached = Some((schema, avro_schema));
+        Ok(avro_schema)
+    }
+
+    pub fn open(&self, cursor: &WriteableCursor, schema: SchemaRef) -> Result<Box<dyn FileWriter>, GenericError> {
+        let avro_schema = self.avro_schema(schema)?;
+        let writer = apache_avro::Writer::with_codec(avro_schema, cursor.try_clone().unwrap(), self.codec);
+        Ok(Box::new(AvroFileWriter { writer }))
+    }
+}
@@ -0,0 +1,150 @@
+use crate::cli::{ArgRequired::False, CmdArg, CmdArgEntry, DefaultValue};
+use crate::util::WriteableCursor;
+use bzip2::write::BzEncoder;
+use clap::ArgMatches;
+use flate2::write::GzEncoder;
+use std::io::{Error, ErrorKind, Result, Write};
+use std::sync::{Arc, Mutex};
+
+enum Codec {
+    Plain(WriteableCursor),
+    Gzip(GzEncoder<WriteableCursor>),
+    Zstd(zstd::stream::write::Encoder<'static, WriteableCursor>),
+    Bzip2(BzEncoder<WriteableCursor>),
+}
+
+impl Codec {
+    fn writer(&mut self) -> &mut dyn Write {
+        match self {
+            Codec::Plain(w) => w,
+            Codec::Gzip(w) => w,
+            Codec::Zstd(w) => w,
+            Codec::Bzip2(w) => w,
+        }
+    }
+}
+
+// A compressing writer. Clones share the stream, so the format writer can own one while
+// another finishes the stream once the format writer is done.
+#[derive(Clone)]
+pub struct Encoder {
+    codec: Arc<Mutex<Option<Codec>>>,
+}
+
+impl Encoder {
+    fn new(codec: Codec) -> Self {
+        Self {
+            codec: Arc::new(Mutex::new(Some(codec))),
+        }
+    }
+
+    // writes the trailer of the compressed stream
+    pub fn finish(&self) -> Result<()> {
+        match self.codec.lock().unwrap().take() {
+            Some(Codec::Gzip(e)) => e.finish().map(drop),
+            Some(Codec::Zstd(e)) => e.finish().map(drop),
+            Some(Codec::Bzip2(e)) => e.finish().map(drop),
+            Some(Codec::Plain(_)) | None => Ok(()),
+        }
+    }
+}
+
+impl Write for Encoder {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        match self.codec.lock().unwrap().as_mut() {
+            Some(codec) => codec.writer().write(buf),
+            None => Err(Error::new(ErrorKind::Other, "write after finish")),
+        }
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        match self.codec.lock().unwrap().as_mut() {
+            Some(codec) => codec.writer().flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+// compression of text output, shared by the CSV and JSON writers
+#[derive(Clone)]
+pub struct OutputCompression {
+    codec: String,
+    level: Option<u32>,
+}
+
+impl OutputCompression {
+    pub fn new(matches: &ArgMatches) -> Self {
+        let codec = String::from(matches.value_of("output-compression").unwrap());
+        if !["none", "gzip", "zstd", "bzip2"].contains(&codec.as_str()) {
+            panic!("unknown output compression: {}", codec);
+        }
+        Self {
+            codec,
+            level: matches
+                .value_of("output-compression-level")
+                .map(|l| l.parse().unwrap()),
+        }
+    }
+
+    pub fn cmd_args() -> CmdArg {
+        CmdArg::new(vec![
+            CmdArgEntry::new(
+                "output-compression",
+                "Compression of output files (none, gzip, zstd or bzip2)",
+                "output-compression",
+                true,
+                False(DefaultValue::String(String::from("none"))),
+            ),
+            CmdArgEntry::new(
+                "output-compression-level",
+                "Compression level (gzip and bzip2: 1-9, zstd: 1-22)",
+                "output-compression-level",
+                true,
+                False(DefaultValue::None),
+            ),
+        ])
+    }
+
+    pub fn encoder(&self, cursor: WriteableCursor) -> Encoder {
+        match self.codec.as_str() {
+            "gzip" => Encoder::new(Codec::Gzip(GzEncoder::new(
+                cursor,
+                flate2::Compression::new(self.level.unwrap_or(6)),
+            ))),
+            "zstd" => Encoder::new(Codec::Zstd(
+                zstd::stream::write::Encoder::new(cursor, self.level.unwrap_or(3) as i32).unwrap(),
+            )),
+            "bzip2" => Encoder::new(Codec::Bzip2(BzEncoder::new(
+                cursor,
+                bzip2::Compression::new(self.level.unwrap_or(6)),
+            ))),
+            _ => Encoder::new(Codec::Plain(cursor)),
+        }
+    }
+
+    // e.g. "csv" -> "csv.gz"
+    pub fn file_extension(&self, extension: &str) -> String {
+        match self.codec.as_str() {
+            "gzip" => format!("{}.gz", extension),
+            "zstd" => format!("{}.zst", extension),
+            "bzip2" => format!("{}.bz2", extension),
+            _ => String::from(extension),
+        }
+    }
+
+    pub fn content_type(&self, content_type: &'static str) -> &'static str {
+        // bzip2 is not an HTTP content coding, so the file is typed as an archive
+        match self.codec.as_str() {
+            "bzip2" => "application/x-bzip2",
+            _ => content_type,
+        }
+    }
+
+    pub fn content_encoding(&self) -> Option<&'static str> {
+        match self.codec.as_str() {
+            "gzip" => Some("gzip"),
+            "zstd" => Some("zstd"),
+            _ => None,
+        }
+    }
+}
@@ -1,42 +1,292 @@
 use crate::cli::{ArgRequired, CmdArg, CmdArgEntry, DefaultValue};
-use crate::util::WriteableCursor;
-use arrow::csv::writer::WriterBuilder;
+use crate::error::GenericError;
+use crate::util::{single_byte, WriteableCursor};
+use crate::writer::compression::{Encoder, OutputCompression};
+use crate::writer::FileWriter;
+use arrow::array::{
+    Array, ArrayRef, Date32Array, Date64Array, Time32MillisecondArray, Time32SecondArray,
+    Time64MicrosecondArray, Time64NanosecondArray, TimestampMicrosecondArray,
+    TimestampMillisecondArray, TimestampNanosecondArray, TimestampSecondArray,
+};
+use arrow::datatypes::{DataType, SchemaRef, TimeUnit};
 use arrow::record_batch::RecordBatch;
+use arrow::util::display::array_value_to_string;
+use chrono::{DateTime, NaiveDateTime, Utc};
 use clap::ArgMatches;
+use std::io::Write;
+use std::sync::Arc;
+
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+macro_rules! temporal_value {
+    ($column:expr, $t:ty, $row:expr, $as:ident) => {
+        $column.as_any().downcast_ref::<$t>().unwrap().$as($row)
+    };
+}
+
+// how values are turned into text
+struct ValueFormat {
+    null_value: String,
+    date_format: String,
+    time_format: String,
+    timestamp_format: String,
+}
+
+impl ValueFormat {
+    fn timestamp(&self, datetime: Option<NaiveDateTime>) -> Option<String> {
+        // formatted as UTC, so that offsets (%z, %:z) can be part of the format
+        let datetime = DateTime::<Utc>::from_utc(datetime?, Utc);
+        Some(datetime.format(&self.timestamp_format).to_string())
+    }
+
+    fn format(&self, column: &ArrayRef, row: usize) -> String {
+        if column.is_null(row) {
+            return self.null_value.clone();
+        }
+        let formatted = match column.data_type() {
+            DataType::Date32 => temporal_value!(column, Date32Array, row, value_as_date)
+                .map(|d| d.format(&self.date_format).to_string()),
+            DataType::Date64 => temporal_value!(column, Date64Array, row, value_as_date)
+                .map(|d| d.format(&self.date_format).to_string()),
+            DataType::Time32(TimeUnit::Second) => {
+                temporal_value!(column, Time32SecondArray, row, value_as_time)
+                    .map(|t| t.format(&self.time_format).to_string())
+            }
+            DataType::Time32(_) => {
+                temporal_value!(column, Time32MillisecondArray, row, value_as_time)
+                    .map(|t| t.format(&self.time_format).to_string())
+            }
+            DataType::Time64(TimeUnit::Microsecond) => {
+                temporal_value!(column, Time64MicrosecondArray, row, value_as_time)
+                    .map(|t| t.format(&self.time_format).to_string())
+            }
+            DataType::Time64(_) => {
+                temporal_value!(column, Time64NanosecondArray, row, value_as_time)
+                    .map(|t| t.format(&self.time_format).to_string())
+            }
+            DataType::Timestamp(TimeUnit::Second, _) => self.timestamp(temporal_value!(
+                column,
+                TimestampSecondArray,
+                row,
+                value_as_datetime
+            )),
+            DataType::Timestamp(TimeUnit::Millisecond, _) => self.timestamp(temporal_value!(
+                column,
+                TimestampMillisecondArray,
+                row,
+                value_as_datetime
+            )),
+            DataType::Timestamp(TimeUnit::Microsecond, _) => self.timestamp(temporal_value!(
+                column,
+                TimestampMicrosecondArray,
+                row,
+                value_as_datetime
+            )),
+            DataType::Timestamp(TimeUnit::Nanosecond, _) => self.timestamp(temporal_value!(
+                column,
+                TimestampNanosecondArray,
+                row,
+                value_as_datetime
+            )),
+            _ => array_value_to_string(column, row).ok(),
+        };
+        formatted.unwrap_or_else(|| self.null_value.clone())
+    }
+}
+
+struct CsvFileWriter {
+    writer: csv::Writer<Encoder>,
+    encoder: Encoder,
+    format: Arc<ValueFormat>,
+}
+
+impl FileWriter for CsvFileWriter {
+    fn write(&mut self, batch: &RecordBatch) {
+        for row in 0..batch.num_rows() {
+            let record = batch.columns().iter().map(|c| self.format.format(c, row));
+            self.writer.write_record(record).expect("Writing batch");
+        }
+    }
+
+    fn close(self: Box<Self>) {
+        let CsvFileWriter { mut writer, encoder, .. } = *self;
+        writer.flush().unwrap();
+        drop(writer);
+        encoder.finish().unwrap();
+    }
+}
 
 #[derive(Clone)]
 pub struct Writer {
     file_extension: String,
     has_header: bool,
+    delimiter: u8,
+    quote_style: csv::QuoteStyle,
+    quote: u8,
+    escape: Option<u8>,
+    terminator: csv::Terminator,
+    bom: bool,
+    format: Arc<ValueFormat>,
+    compression: OutputCompression,
 }
 
 impl Writer {
     pub fn new(matches: &ArgMatches) -> Self {
         let has_header = matches.is_present("output-csv-header");
+        let quote_style = match matches.value_of("output-csv-quote-style").unwrap() {
+            "always" => csv::QuoteStyle::Always,
+            "necessary" => csv::QuoteStyle::Necessary,
+            "non-numeric" => csv::QuoteStyle::NonNumeric,
+            "never" => csv::QuoteStyle::Never,
+            unknown => panic!("unknown quote style: {}", unknown),
+        };
+        let terminator = match matches.value_of("output-csv-line-terminator").unwrap() {
+            "lf" => csv::Terminator::Any(b'\n'),
+            "crlf" => csv::Terminator::CRLF,
+            unknown => panic!("unknown line terminator: {}", unknown),
+        };
+        let format = ValueFormat {
+            null_value: String::from(matches.value_of("output-csv-null").unwrap()),
+            date_format: String::from(matches.value_of("output-csv-date-format").unwrap()),
+            time_format: String::from(matches.value_of("output-csv-time-format").unwrap()),
+            timestamp_format: String::from(matches.value_of("output-csv-timestamp-format").unwrap()),
+        };
+        let compression = OutputCompression::new(matches);
         Self {
-            file_extension: String::from("csv"),
+            file_extension: compression.file_extension("csv"),
             has_header,
+            delimiter: single_byte("delimiter", matches.value_of("output-csv-delimiter").unwrap()),
+            quote_style,
+            quote: single_byte("quote", matches.value_of("output-csv-quote").unwrap()),
+            escape: matches
+                .value_of("output-csv-escape")
+                .map(|e| single_byte("escape", e)),
+            terminator,
+            bom: matches.is_present("output-csv-bom"),
+            format: Arc::new(format),
+            compression,
         }
     }
 
     pub fn cmd_args() -> CmdArg {
-        CmdArg::new(vec![CmdArgEntry::new(
-            "output-csv-header",
-            "Output CSV has header",
-            "output-csv-header",
-            false,
-            ArgRequired::False(DefaultValue::Bool(false)),
-        )])
+        let mut arg_entries = vec![
+            CmdArgEntry::new(
+                "output-csv-header",
+                "Output CSV has header",
+                "output-csv-header",
+                false,
+                ArgRequired::False(DefaultValue::Bool(false)),
+            ),
+            CmdArgEntry::new(
+                "output-csv-delimiter",
+                "Delimiter of output CSV ('\\t' for TSV)",
+                "output-csv-delimiter",
+                true,
+                ArgRequired::False(DefaultValue::String(String::from(","))),
+            ),
+            CmdArgEntry::new(
+                "output-csv-quote-style",
+                "When to quote fields (always, necessary, non-numeric or never)",
+                "output-csv-quote-style",
+                true,
+                ArgRequired::False(DefaultValue::String(String::from("necessary"))),
+            ),
+            CmdArgEntry::new(
+                "output-csv-quote",
+                "Quote character of output CSV",
+                "output-csv-quote",
+                true,
+                ArgRequired::False(DefaultValue::String(String::from("\""))),
+            ),
+            CmdArgEntry::new(
+                "output-csv-escape",
+                "Escape character for quotes, quotes are doubled if omitted",
+                "output-csv-escape",
+                true,
+                ArgRequired::False(DefaultValue::None),
+            ),
+            CmdArgEntry::new(
+                "output-csv-null",
+                "String written for null (e.g. \\N)",
+                "output-csv-null",
+                true,
+                ArgRequired::False(DefaultValue::String(String::new())),
+            ),
+            CmdArgEntry::new(
+                "output-csv-date-format",
+                "strftime format of dates",
+                "output-csv-date-format",
+                true,
+                ArgRequired::False(DefaultValue::String(String::from("%Y-%m-%d"))),
+            ),
+            CmdArgEntry::new(
+                "output-csv-time-format",
+                "strftime format of times",
+                "output-csv-time-format",
+                true,
+                ArgRequired::False(DefaultValue::String(String::from("%H:%M:%S%.f"))),
+            ),
+            CmdArgEntry::new(
+                "output-csv-timestamp-format",
+                "strftime format of timestamps, in UTC",
+                "output-csv-timestamp-format",
+                true,
+                ArgRequired::False(DefaultValue::String(String::from("%Y-%m-%dT%H:%M:%S%.9f"))),
+            ),
+            CmdArgEntry::new(
+                "output-csv-line-terminator",
+                "Line terminator (lf or crlf)",
+                "output-csv-line-terminator",
+                true,
+                ArgRequired::False(DefaultValue::String(String::from("lf"))),
+            ),
+            CmdArgEntry::new(
+                "output-csv-bom",
+                "Start output CSV with a UTF-8 byte order mark",
+                "output-csv-bom",
+                false,
+                ArgRequired::False(DefaultValue::Bool(false)),
+            ),
+        ];
+        arg_entries.extend_from_slice(&OutputCompression::cmd_args().entries());
+        CmdArg::new(arg_entries)
     }
 
     pub fn file_extension(&self) -> &String {
         &self.file_extension
     }
 
-    pub fn write(&self, cursor: &WriteableCursor, batch: RecordBatch) {
-        let builder = WriterBuilder::new().has_headers(self.has_header);
-        let mut writer = builder.build(cursor.try_clone().unwrap());
-        writer.write(&batch).expect("Writing batch");
+    pub fn content_type(&self) -> &'static str {
+        self.compression.content_type("text/csv")
+    }
+
+    pub fn content_encoding(&self) -> Option<&'static str> {
+        self.compression.content_encoding()
+    }
+
+    pub fn open(&self, cursor: &WriteableCursor, schema: SchemaRef) -> Result<Box<dyn FileWriter>, GenericError> {
+        let mut encoder = self.compression.encoder(cursor.try_clone().unwrap());
+        if self.bom {
+            encoder.write_all(UTF8_BOM).unwrap();
+        }
+        let mut writer = csv::WriterBuilder::new()
+            .delimiter(self.delimiter)
+            .quote_style(self.quote_style)
+            .quote(self.quote)
+            .double_quote(self.escape.is_none())
+            .escape(self.escape.unwrap_or(b'\\'))
+            .terminator(self.terminator)
+            .from_writer(encoder.clone());
+        if self.has_header {
+            writer
+                .write_record(schema.fields().iter().map(|f| f.name()))
+                .expect("Writing header");
+        }
+        Ok(Box::new(CsvFileWriter {
+            writer,
+            encoder,
+            format: self.format.clone(),
+        }))
     }
 }
 
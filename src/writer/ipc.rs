@@ -0,0 +1,113 @@
+use crate::cli::{ArgRequired::False, CmdArg, CmdArgEntry, DefaultValue};
+use crate::error::GenericError;
+use crate::util::WriteableCursor;
+use crate::writer::FileWriter;
+use arrow::datatypes::SchemaRef;
+use arrow::error::ArrowError;
+use arrow::ipc::writer::{FileWriter as IpcWriter, IpcWriteOptions, StreamWriter};
+use arrow::ipc::{CompressionType, MetadataVersion};
+use arrow::record_batch::RecordBatch;
+use clap::ArgMatches;
+
+enum IpcFileWriter {
+    File(IpcWriter<WriteableCursor>),
+    Stream(StreamWriter<WriteableCursor>),
+}
+
+impl FileWriter for IpcFileWriter {
+    fn write(&mut self, batch: &RecordBatch) {
+        match self {
+            IpcFileWriter::File(w) => w.write(batch),
+            IpcFileWriter::Stream(w) => w.write(batch),
+        }
+        .expect("Writing batch");
+    }
+
+    fn close(self: Box<Self>) {
+        match *self {
+            IpcFileWriter::File(mut w) => w.finish(),
+            IpcFileWriter::Stream(mut w) => w.finish(),
+        }
+        .unwrap();
+    }
+}
+
+#[derive(Clone)]
+pub struct Writer {
+    stream_format: bool,
+    options: IpcWriteOptions,
+    file_extension: String,
+}
+
+impl Writer {
+    pub fn new(matches: &ArgMatches) -> Self {
+        let stream_format = match matches.value_of("output-arrow-format").unwrap() {
+            "file" => false,
+            "stream" => true,
+            unknown => panic!("unknown Arrow IPC format: {}", unknown),
+        };
+        let compression = match matches.value_of("compression").unwrap() {
+            "none" => None,
+            "lz4" => Some(CompressionType::LZ4_FRAME),
+            "zstd" => Some(CompressionType::ZSTD),
+            unknown => panic!("unknown Arrow IPC compression: {}", unknown),
+        };
+        let options = IpcWriteOptions::try_new(8, false, MetadataVersion::V5)
+            .and_then(|o| o.try_with_compression(compression))
+            .unwrap();
+        Self {
+            stream_format,
+            options,
+            file_extension: String::from(if stream_format { "arrows" } else { "arrow" }),
+        }
+    }
+
+    pub fn cmd_args() -> CmdArg {
+        CmdArg::new(vec![
+            CmdArgEntry::new(
+                "output-arrow-format",
+                "Arrow IPC format (file or stream)",
+                "output-arrow-format",
+                true,
+                False(DefaultValue::String(String::from("file"))),
+            ),
+            CmdArgEntry::new(
+                "compression",
+                "Buffer compression (none, lz4 or zstd)",
+                "compression",
+                true,
+                False(DefaultValue::String(String::from("none"))),
+            ),
+        ])
+    }
+
+    pub fn file_extension(&self) -> &String {
+        &self.file_extension
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        if self.stream_format {
+            "application/vnd.apache.arrow.stream"
+        } else {
+            "application/vnd.apache.arrow.file"
+        }
+    }
+
+    pub fn content_encoding(&self) -> Option<&'static str> {
+        None
+    }
+
+    pub fn open(&self, cursor: &WriteableCursor, schema: SchemaRef) -> Result<Box<dyn FileWriter>, GenericError> {
+        let cursor = cursor.try_clone().unwrap();
+        let open_error = |e: ArrowError| GenericError {
+            message: format!("Arrow IPC writer error: {}", e),
+        };
+        if self.stream_format {
+            let writer = StreamWriter::try_new_with_options(cursor, &schema, self.options.clone());
+            Ok(Box::new(IpcFileWriter::Stream(writer.map_err(open_error)?)))
+        } else {
+            let writer = IpcWriter::try_new_with_options(cursor, &schema, self.options.clone());
+            Ok(Box::new(IpcFileWriter::File(writer.map_err(open_error)?)))
+        }
+    }
+}
@@ -1,35 +1,273 @@
-use crate::cli::CmdArg;
+use crate::cli::{ArgRequired, CmdArg, CmdArgEntry, DefaultValue};
+use crate::error::GenericError;
 use crate::util::WriteableCursor;
-use arrow::json::writer::LineDelimitedWriter;
+use crate::writer::compression::{Encoder, OutputCompression};
+use crate::writer::FileWriter;
+use arrow::array::{
+    Array, ArrayRef, BinaryArray, BooleanArray, Date32Array, Date64Array, DecimalArray,
+    FixedSizeBinaryArray, Float32Array, Float64Array, Int16Array, Int32Array, Int64Array,
+    Int8Array, LargeBinaryArray, LargeListArray, LargeStringArray, ListArray, StringArray,
+    StructArray, Time32MillisecondArray, Time32SecondArray, Time64MicrosecondArray,
+    Time64NanosecondArray, TimestampMicrosecondArray, TimestampMillisecondArray,
+    TimestampNanosecondArray, TimestampSecondArray, UInt16Array, UInt32Array, UInt64Array,
+    UInt8Array,
+};
+use arrow::datatypes::{DataType, Field, SchemaRef, TimeUnit};
 use arrow::record_batch::RecordBatch;
+use arrow::util::display::array_value_to_string;
+use chrono::NaiveDateTime;
 use clap::ArgMatches;
+use serde_json::{Map, Number, Value};
+use std::io::{BufWriter, Write};
+
+macro_rules! array {
+    ($column:expr, $t:ty) => {
+        $column.as_any().downcast_ref::<$t>().unwrap()
+    };
+}
+
+// BigQuery reads TIMESTAMP as RFC 3339 and DATETIME without a zone
+fn timestamp_value(datetime: Option<NaiveDateTime>, has_timezone: bool) -> Value {
+    match datetime {
+        Some(dt) if has_timezone => Value::from(dt.format("%Y-%m-%dT%H:%M:%S%.6fZ").to_string()),
+        Some(dt) => Value::from(dt.format("%Y-%m-%dT%H:%M:%S%.6f").to_string()),
+        None => Value::Null,
+    }
+}
+
+fn float_value(value: f64) -> Value {
+    Number::from_f64(value).map_or(Value::Null, Value::Number)
+}
+
+#[derive(Clone, Copy)]
+struct ValueFormat {
+    explicit_nulls: bool,
+}
+
+impl ValueFormat {
+    fn record(&self, fields: &[Field], columns: &[ArrayRef], row: usize) -> Value {
+        let mut record = Map::new();
+        for (field, column) in fields.iter().zip(columns) {
+            let value = self.value(column, row);
+            if self.explicit_nulls || !value.is_null() {
+                record.insert(field.name().clone(), value);
+            }
+        }
+        Value::Object(record)
+    }
+
+    fn value(&self, column: &ArrayRef, row: usize) -> Value {
+        if column.is_null(row) {
+            return Value::Null;
+        }
+        match column.data_type() {
+            DataType::Null => Value::Null,
+            DataType::Boolean => Value::from(array!(column, BooleanArray).value(row)),
+            DataType::Int8 => Value::from(array!(column, Int8Array).value(row)),
+            DataType::Int16 => Value::from(array!(column, Int16Array).value(row)),
+            DataType::Int32 => Value::from(array!(column, Int32Array).value(row)),
+            DataType::Int64 => Value::from(array!(column, Int64Array).value(row)),
+            DataType::UInt8 => Value::from(array!(column, UInt8Array).value(row)),
+            DataType::UInt16 => Value::from(array!(column, UInt16Array).value(row)),
+            DataType::UInt32 => Value::from(array!(column, UInt32Array).value(row)),
+            DataType::UInt64 => Value::from(array!(column, UInt64Array).value(row)),
+            DataType::Float32 => float_value(array!(column, Float32Array).value(row) as f64),
+            DataType::Float64 => float_value(array!(column, Float64Array).value(row)),
+            DataType::Utf8 => Value::from(array!(column, StringArray).value(row)),
+            DataType::LargeUtf8 => Value::from(array!(column, LargeStringArray).value(row)),
+            // BYTES are base64 encoded
+            DataType::Binary => Value::from(base64::encode(array!(column, BinaryArray).value(row))),
+            DataType::LargeBinary => {
+                Value::from(base64::encode(array!(column, LargeBinaryArray).value(row)))
+            }
+            DataType::FixedSizeBinary(_) => {
+                Value::from(base64::encode(array!(column, FixedSizeBinaryArray).value(row)))
+            }
+            // NUMERIC is written as a string so that no precision is lost
+            DataType::Decimal(_, _) => Value::from(array!(column, DecimalArray).value_as_string(row)),
+            DataType::Date32 => array!(column, Date32Array)
+                .value_as_date(row)
+                .map_or(Value::Null, |d| Value::from(d.format("%Y-%m-%d").to_string())),
+            DataType::Date64 => array!(column, Date64Array)
+                .value_as_date(row)
+                .map_or(Value::Null, |d| Value::from(d.format("%Y-%m-%d").to_string())),
+            DataType::Time32(TimeUnit::Second) => array!(column, Time32SecondArray)
+                .value_as_time(row)
+                .map_or(Value::Null, |t| Value::from(t.format("%H:%M:%S").to_string())),
+            DataType::Time32(_) => array!(column, Time32MillisecondArray)
+                .value_as_time(row)
+                .map_or(Value::Null, |t| Value::from(t.format("%H:%M:%S%.3f").to_string())),
+            DataType::Time64(TimeUnit::Microsecond) => array!(column, Time64MicrosecondArray)
+                .value_as_time(row)
+                .map_or(Value::Null, |t| Value::from(t.format("%H:%M:%S%.6f").to_string())),
+            DataType::Time64(_) => array!(column, Time64NanosecondArray)
+                .value_as_time(row)
+                .map_or(Value::Null, |t| Value::from(t.format("%H:%M:%S%.6f").to_string())),
+            DataType::Timestamp(unit, tz) => {
+                let datetime = match unit {
+                    TimeUnit::Second => array!(column, TimestampSecondArray).value_as_datetime(row),
+                    TimeUnit::Millisecond => {
+                        array!(column, TimestampMillisecondArray).value_as_datetime(row)
+                    }
+                    TimeUnit::Microsecond => {
+                        array!(column, TimestampMicrosecondArray).value_as_datetime(row)
+                    }
+                    TimeUnit::Nanosecond => {
+                        array!(column, TimestampNanosecondArray).value_as_datetime(row)
+                    }
+                };
+                timestamp_value(datetime, tz.is_some())
+            }
+            DataType::List(_) => {
+                let values = array!(column, ListArray).value(row);
+                Value::Array((0..values.len()).map(|i| self.value(&values, i)).collect())
+            }
+            DataType::LargeList(_) => {
+                let values = array!(column, LargeListArray).value(row);
+                Value::Array((0..values.len()).map(|i| self.value(&values, i)).collect())
+            }
+            DataType::Struct(fields) => {
+                let columns: Vec<ArrayRef> = array!(column, StructArray)
+                    .columns()
+                    .into_iter()
+                    .cloned()
+                    .collect();
+                self.record(fields, &columns, row)
+            }
+            _ => array_value_to_string(column, row).map_or(Value::Null, Value::from),
+        }
+    }
+}
+
+struct JsonFileWriter {
+    // records are serialized in small writes, which the shared encoder would lock for each
+    writer: BufWriter<Encoder>,
+    encoder: Encoder,
+    array: bool,
+    pretty: bool,
+    format: ValueFormat,
+    num_records: usize,
+}
+
+impl FileWriter for JsonFileWriter {
+    fn write(&mut self, batch: &RecordBatch) {
+        let schema = batch.schema();
+        for row in 0..batch.num_rows() {
+            let record = self.format.record(schema.fields(), batch.columns(), row);
+            if self.array && self.num_records > 0 {
+                self.writer.write_all(b",\n").expect("Writing batch");
+            }
+            if self.pretty {
+                serde_json::to_writer_pretty(&mut self.writer, &record).expect("Writing batch");
+            } else {
+                serde_json::to_writer(&mut self.writer, &record).expect("Writing batch");
+            }
+            if !self.array {
+                self.writer.write_all(b"\n").expect("Writing batch");
+            }
+            self.num_records += 1;
+        }
+    }
+
+    fn close(self: Box<Self>) {
+        let JsonFileWriter { mut writer, encoder, array, .. } = *self;
+        if array {
+            writer.write_all(b"\n]\n").unwrap();
+        }
+        writer.flush().unwrap();
+        drop(writer);
+        encoder.finish().unwrap();
+    }
+}
 
 #[derive(Clone)]
 pub struct Writer {
     file_extension: String,
+    array: bool,
+    pretty: bool,
+    format: ValueFormat,
+    compression: OutputCompression,
 }
 
 impl Writer {
-    pub fn new(_matches: &ArgMatches) -> Self {
+    pub fn new(matches: &ArgMatches) -> Self {
+        let array = match matches.value_of("json-format").unwrap() {
+            "ndjson" => false,
+            "array" => true,
+            unknown => panic!("unknown JSON format: {}", unknown),
+        };
+        let explicit_nulls = match matches.value_of("json-nulls").unwrap() {
+            "omit" => false,
+            "explicit" => true,
+            unknown => panic!("unknown JSON null handling: {}", unknown),
+        };
+        let compression = OutputCompression::new(matches);
         Self {
-            file_extension: String::from("json"),
+            file_extension: compression.file_extension("json"),
+            array,
+            pretty: matches.is_present("json-pretty"),
+            format: ValueFormat { explicit_nulls },
+            compression,
         }
     }
 
     pub fn cmd_args() -> CmdArg {
-        CmdArg::new(vec![])
+        let mut arg_entries = vec![
+            CmdArgEntry::new(
+                "json-format",
+                "Newline delimited records (ndjson) or a single JSON array (array)",
+                "json-format",
+                true,
+                ArgRequired::False(DefaultValue::String(String::from("ndjson"))),
+            ),
+            CmdArgEntry::new(
+                "json-pretty",
+                "Pretty print records",
+                "json-pretty",
+                false,
+                ArgRequired::False(DefaultValue::Bool(false)),
+            ),
+            CmdArgEntry::new(
+                "json-nulls",
+                "Write null values as explicit nulls (explicit) or leave the keys out (omit)",
+                "json-nulls",
+                true,
+                ArgRequired::False(DefaultValue::String(String::from("omit"))),
+            ),
+        ];
+        arg_entries.extend_from_slice(&OutputCompression::cmd_args().entries());
+        CmdArg::new(arg_entries)
     }
 
     pub fn file_extension(&self) -> &String {
         &self.file_extension
     }
 
-    pub fn write(&self, cursor: &WriteableCursor, batch: RecordBatch) {
-        let mut writer = LineDelimitedWriter::new(
-            cursor.try_clone().unwrap(),
-        );
-        writer.write(batch).expect("Writing batch");
-        writer.finish().unwrap();
+    pub fn content_type(&self) -> &'static str {
+        if self.array {
+            self.compression.content_type("application/json")
+        } else {
+            self.compression.content_type("application/x-ndjson")
+        }
+    }
+
+    pub fn content_encoding(&self) -> Option<&'static str> {
+        self.compression.content_encoding()
+    }
+
+    pub fn open(&self, cursor: &WriteableCursor, _schema: SchemaRef) -> Result<Box<dyn FileWriter>, GenericError> {
+        let mut encoder = self.compression.encoder(cursor.try_clone().unwrap());
+        if self.array {
+            encoder.write_all(b"[\n").unwrap();
+        }
+        Ok(Box::new(JsonFileWriter {
+            writer: BufWriter::new(encoder.clone()),
+            encoder,
+            array: self.array,
+            pretty: self.pretty,
+            format: self.format,
+            num_records: 0,
+        }))
     }
 }
 
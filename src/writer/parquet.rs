@@ -1,12 +1,109 @@
 use crate::cli::{ArgRequired::False, CmdArg, CmdArgEntry, DefaultValue};
+use crate::error::GenericError;
 use crate::util::WriteableCursor;
+use crate::writer::FileWriter;
+use arrow::datatypes::SchemaRef;
 use arrow::record_batch::RecordBatch;
 use clap::ArgMatches;
 
 use parquet::{
-    arrow::arrow_writer::ArrowWriter, basic::Compression, file::properties::WriterProperties,
+    arrow::arrow_writer::ArrowWriter,
+    basic::{Compression, Encoding},
+    file::metadata::KeyValue,
+    file::properties::{WriterProperties, WriterVersion},
+    schema::types::ColumnPath,
 };
 
+fn compression(name: &str) -> Compression {
+    match name.to_lowercase().as_str() {
+        "none" | "uncompressed" => Compression::UNCOMPRESSED,
+        "snappy" => Compression::SNAPPY,
+        "gzip" => Compression::GZIP,
+        "lzo" => Compression::LZO,
+        "brotli" => Compression::BROTLI,
+        "lz4" => Compression::LZ4,
+        "zstd" => Compression::ZSTD,
+        // parquet 15 compresses at each codec's default level, levels wait for an upgrade
+        leveled if leveled.contains(':') => {
+            panic!("Parquet compression levels need a newer parquet crate: {}", leveled)
+        }
+        unknown => panic!("unknown Parquet compression: {}", unknown),
+    }
+}
+
+fn encoding(name: &str) -> Encoding {
+    match name.to_uppercase().as_str() {
+        "PLAIN" => Encoding::PLAIN,
+        "RLE" => Encoding::RLE,
+        "DELTA_BINARY_PACKED" => Encoding::DELTA_BINARY_PACKED,
+        "DELTA_LENGTH_BYTE_ARRAY" => Encoding::DELTA_LENGTH_BYTE_ARRAY,
+        "DELTA_BYTE_ARRAY" => Encoding::DELTA_BYTE_ARRAY,
+        "BYTE_STREAM_SPLIT" => Encoding::BYTE_STREAM_SPLIT,
+        unknown => panic!("unknown Parquet encoding: {}", unknown),
+    }
+}
+
+fn bool_value(name: &str, value: &str) -> bool {
+    value
+        .parse()
+        .unwrap_or_else(|_| panic!("{} must be true or false: {}", name, value))
+}
+
+// "a=1,b.c=2" -> [("a", "1"), ("b.c", "2")]
+fn pairs(value: Option<&str>) -> Vec<(String, String)> {
+    value
+        .map(|v| {
+            v.split(',')
+                .map(|pair| {
+                    let (key, value) = pair.split_once('=').expect("expected KEY=VALUE");
+                    (String::from(key.trim()), String::from(value.trim()))
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// nested columns are given with dots, e.g. "address.city"
+fn column_path(name: &str) -> ColumnPath {
+    ColumnPath::new(name.split('.').map(String::from).collect())
+}
+
+// rows are buffered until --parquet-max-row-group-size, so a file can hold many row groups
+struct ParquetFileWriter {
+    writer: ArrowWriter<WriteableCursor>,
+    max_row_group_size: usize,
+    // rows not yet flushed as a row group and their in-memory size
+    buffered_rows: usize,
+    buffered_bytes: usize,
+}
+
+impl FileWriter for ParquetFileWriter {
+    fn write(&mut self, batch: &RecordBatch) {
+        self.writer.write(batch).expect("Writing batch");
+        self.buffered_rows += batch.num_rows();
+        self.buffered_bytes += batch
+            .columns()
+            .iter()
+            .map(|column| column.get_array_memory_size())
+            .sum::<usize>();
+        // ArrowWriter flushes a row group for every max_row_group_size rows
+        if self.buffered_rows >= self.max_row_group_size {
+            let rest = self.buffered_rows % self.max_row_group_size;
+            self.buffered_bytes = self.buffered_bytes / self.buffered_rows * rest;
+            self.buffered_rows = rest;
+        }
+    }
+
+    fn close(self: Box<Self>) {
+        self.writer.close().unwrap();
+    }
+
+    // the Arrow size of the buffered rows, an upper bound of their encoded size
+    fn buffered_size(&self) -> usize {
+        self.buffered_bytes
+    }
+}
+
 #[derive(Clone)]
 pub struct Writer {
     properties: WriterProperties,
@@ -15,41 +112,179 @@ pub struct Writer {
 
 impl Writer {
     pub fn new(matches: &ArgMatches) -> Self {
-        let compression = match matches.value_of("compression").unwrap() {
-            "snappy" => Compression::SNAPPY,
-            _ => Compression::SNAPPY,
+        let writer_version = match matches.value_of("parquet-writer-version").unwrap() {
+            "1.0" => WriterVersion::PARQUET_1_0,
+            "2.0" => WriterVersion::PARQUET_2_0,
+            unknown => panic!("unknown Parquet writer version: {}", unknown),
         };
+        // parquet 15 only switches statistics on or off, and then writes them per page;
+        // chunk-only statistics wait for an upgrade
+        let statistics = match matches.value_of("parquet-statistics").unwrap() {
+            "none" => false,
+            "page" => true,
+            unknown => panic!("unknown Parquet statistics level: {}", unknown),
+        };
+        let mut builder = WriterProperties::builder()
+            .set_compression(compression(matches.value_of("compression").unwrap()))
+            .set_writer_version(writer_version)
+            .set_dictionary_enabled(bool_value(
+                "parquet-dictionary",
+                matches.value_of("parquet-dictionary").unwrap(),
+            ))
+            .set_statistics_enabled(statistics);
+        if let Some(size) = matches.value_of("parquet-max-row-group-size") {
+            builder = builder.set_max_row_group_size(size.parse().unwrap());
+        }
+        if let Some(size) = matches.value_of("parquet-data-page-size") {
+            builder = builder.set_data_pagesize_limit(size.parse().unwrap());
+        }
+        if let Some(name) = matches.value_of("parquet-encoding") {
+            builder = builder.set_encoding(encoding(name));
+        }
+        for (column, name) in pairs(matches.value_of("parquet-column-compression")) {
+            builder = builder.set_column_compression(column_path(&column), compression(&name));
+        }
+        for (column, name) in pairs(matches.value_of("parquet-column-encoding")) {
+            builder = builder.set_column_encoding(column_path(&column), encoding(&name));
+        }
+        for (column, enabled) in pairs(matches.value_of("parquet-column-dictionary")) {
+            builder = builder.set_column_dictionary_enabled(
+                column_path(&column),
+                bool_value("parquet-column-dictionary", &enabled),
+            );
+        }
+        if let Some(created_by) = matches.value_of("parquet-created-by") {
+            builder = builder.set_created_by(String::from(created_by));
+        }
+        let metadata: Vec<KeyValue> = pairs(matches.value_of("parquet-metadata"))
+            .into_iter()
+            .map(|(key, value)| KeyValue::new(key, Some(value)))
+            .collect();
+        if !metadata.is_empty() {
+            builder = builder.set_key_value_metadata(Some(metadata));
+        }
         Self {
-            properties: WriterProperties::builder()
-                .set_compression(compression)
-                .build(),
+            properties: builder.build(),
             file_extension: String::from("parquet"),
         }
     }
 
     pub fn cmd_args() -> CmdArg {
-        CmdArg::new(vec![CmdArgEntry::new(
-            "compression",
-            "Compression type",
-            "compression",
-            true,
-            False(DefaultValue::String(String::from("snappy"))),
-        )])
+        CmdArg::new(vec![
+            CmdArgEntry::new(
+                "compression",
+                "Compression type (none, snappy, gzip, lzo, brotli, lz4 or zstd)",
+                "compression",
+                true,
+                False(DefaultValue::String(String::from("snappy"))),
+            ),
+            CmdArgEntry::new(
+                "parquet-column-compression",
+                "Compression per column, e.g. 'payload=zstd,id=none'",
+                "parquet-column-compression",
+                true,
+                False(DefaultValue::None),
+            ),
+            CmdArgEntry::new(
+                "parquet-writer-version",
+                "Parquet writer version (1.0 or 2.0)",
+                "parquet-writer-version",
+                true,
+                False(DefaultValue::String(String::from("1.0"))),
+            ),
+            CmdArgEntry::new(
+                "parquet-max-row-group-size",
+                "Maximum number of rows in a row group",
+                "parquet-max-row-group-size",
+                true,
+                False(DefaultValue::None),
+            ),
+            CmdArgEntry::new(
+                "parquet-data-page-size",
+                "Data page size limit in bytes",
+                "parquet-data-page-size",
+                true,
+                False(DefaultValue::None),
+            ),
+            CmdArgEntry::new(
+                "parquet-dictionary",
+                "Dictionary encoding (true or false)",
+                "parquet-dictionary",
+                true,
+                False(DefaultValue::String(String::from("true"))),
+            ),
+            CmdArgEntry::new(
+                "parquet-column-dictionary",
+                "Dictionary encoding per column, e.g. 'id=false,country=true'",
+                "parquet-column-dictionary",
+                true,
+                False(DefaultValue::None),
+            ),
+            CmdArgEntry::new(
+                "parquet-encoding",
+                "Encoding used when dictionary is off (e.g. PLAIN, DELTA_BINARY_PACKED)",
+                "parquet-encoding",
+                true,
+                False(DefaultValue::None),
+            ),
+            CmdArgEntry::new(
+                "parquet-column-encoding",
+                "Encoding per column, e.g. 'ts=DELTA_BINARY_PACKED'",
+                "parquet-column-encoding",
+                true,
+                False(DefaultValue::None),
+            ),
+            CmdArgEntry::new(
+                "parquet-statistics",
+                "Statistics level (none or page)",
+                "parquet-statistics",
+                true,
+                False(DefaultValue::String(String::from("page"))),
+            ),
+            CmdArgEntry::new(
+                "parquet-created-by",
+                "created_by of the file metadata",
+                "parquet-created-by",
+                true,
+                False(DefaultValue::None),
+            ),
+            CmdArgEntry::new(
+                "parquet-metadata",
+                "Key-value metadata of the file, e.g. 'run_id=42,source=orders'",
+                "parquet-metadata",
+                true,
+                False(DefaultValue::None),
+            ),
+        ])
     }
 
     pub fn file_extension(&self) -> &String {
         &self.file_extension
     }
 
-    pub fn write(&self, cursor: &WriteableCursor, batch: RecordBatch) {
+    pub fn content_type(&self) -> &'static str {
+        "application/vnd.apache.parquet"
+    }
+
+    pub fn content_encoding(&self) -> Option<&'static str> {
+        None
+    }
+
+    pub fn open(&self, cursor: &WriteableCursor, schema: SchemaRef) -> Result<Box<dyn FileWriter>, GenericError> {
         // async writer not supported yet: https://github.com/apache/arrow-rs/issues/1269
-        let mut writer = ArrowWriter::try_new(
+        let writer = ArrowWriter::try_new(
             cursor.try_clone().unwrap(),
-            batch.schema(),
+            schema,
             Some(self.properties.clone()),
         )
-        .unwrap();
-        writer.write(&batch).expect("Writing batch");
-        writer.close().unwrap();
+        .map_err(|e| GenericError {
+            message: format!("Parquet writer error: {}", e),
+        })?;
+        Ok(Box::new(ParquetFileWriter {
+            writer,
+            max_row_group_size: self.properties.max_row_group_size(),
+            buffered_rows: 0,
+            buffered_bytes: 0,
+        }))
     }
 }